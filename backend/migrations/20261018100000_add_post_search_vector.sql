@@ -0,0 +1,7 @@
+-- Full-text search over post descriptions
+-- The generated column keeps the tsvector in sync without triggers
+
+ALTER TABLE posts ADD COLUMN search_vector TSVECTOR
+    GENERATED ALWAYS AS (to_tsvector('english', description)) STORED;
+
+CREATE INDEX idx_posts_search_vector_gin ON posts USING GIN(search_vector);
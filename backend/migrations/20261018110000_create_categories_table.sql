@@ -0,0 +1,155 @@
+-- Canonical category taxonomy, previously only defined in the frontend
+-- Posts keep storing category names in their TEXT[] column; the API validates against this table
+
+CREATE TABLE categories (
+    name TEXT PRIMARY KEY,
+    group_name TEXT NOT NULL,
+    position INTEGER NOT NULL
+);
+
+INSERT INTO categories (name, group_name, position) VALUES
+    ('Web Development', 'Technology & Programming', 1),
+    ('Mobile App Development', 'Technology & Programming', 2),
+    ('Software Engineering', 'Technology & Programming', 3),
+    ('Data Science', 'Technology & Programming', 4),
+    ('Artificial Intelligence', 'Technology & Programming', 5),
+    ('Machine Learning', 'Technology & Programming', 6),
+    ('Cybersecurity', 'Technology & Programming', 7),
+    ('Cloud Computing', 'Technology & Programming', 8),
+    ('DevOps', 'Technology & Programming', 9),
+    ('Database Management', 'Technology & Programming', 10),
+    ('UI/UX Design', 'Technology & Programming', 11),
+    ('Game Development', 'Technology & Programming', 12),
+    ('Blockchain', 'Technology & Programming', 13),
+    ('IoT Development', 'Technology & Programming', 14),
+    ('System Administration', 'Technology & Programming', 15),
+    ('Graphic Design', 'Creative & Design', 16),
+    ('Video Editing', 'Creative & Design', 17),
+    ('Photography', 'Creative & Design', 18),
+    ('Digital Art', 'Creative & Design', 19),
+    ('Animation', 'Creative & Design', 20),
+    ('Content Writing', 'Creative & Design', 21),
+    ('Copywriting', 'Creative & Design', 22),
+    ('Social Media Management', 'Creative & Design', 23),
+    ('Brand Strategy', 'Creative & Design', 24),
+    ('Logo Design', 'Creative & Design', 25),
+    ('Web Design', 'Creative & Design', 26),
+    ('Print Design', 'Creative & Design', 27),
+    ('Illustration', 'Creative & Design', 28),
+    ('3D Modeling', 'Creative & Design', 29),
+    ('Audio Production', 'Creative & Design', 30),
+    ('Business Strategy', 'Business & Finance', 31),
+    ('Marketing', 'Business & Finance', 32),
+    ('Sales', 'Business & Finance', 33),
+    ('Accounting', 'Business & Finance', 34),
+    ('Financial Planning', 'Business & Finance', 35),
+    ('Project Management', 'Business & Finance', 36),
+    ('Product Management', 'Business & Finance', 37),
+    ('Operations Management', 'Business & Finance', 38),
+    ('Supply Chain', 'Business & Finance', 39),
+    ('Consulting', 'Business & Finance', 40),
+    ('Market Research', 'Business & Finance', 41),
+    ('Investment Advice', 'Business & Finance', 42),
+    ('Tax Preparation', 'Business & Finance', 43),
+    ('Bookkeeping', 'Business & Finance', 44),
+    ('Business Analytics', 'Business & Finance', 45),
+    ('Tutoring', 'Education & Training', 46),
+    ('Language Teaching', 'Education & Training', 47),
+    ('Online Course Creation', 'Education & Training', 48),
+    ('Curriculum Development', 'Education & Training', 49),
+    ('Academic Writing', 'Education & Training', 50),
+    ('Exam Preparation', 'Education & Training', 51),
+    ('Skills Training', 'Education & Training', 52),
+    ('Professional Development', 'Education & Training', 53),
+    ('Corporate Training', 'Education & Training', 54),
+    ('Educational Technology', 'Education & Training', 55),
+    ('Research Assistance', 'Education & Training', 56),
+    ('Thesis Writing', 'Education & Training', 57),
+    ('Presentation Skills', 'Education & Training', 58),
+    ('Study Techniques', 'Education & Training', 59),
+    ('Career Counseling', 'Education & Training', 60),
+    ('Fitness Training', 'Health & Wellness', 61),
+    ('Nutrition Counseling', 'Health & Wellness', 62),
+    ('Mental Health Support', 'Health & Wellness', 63),
+    ('Yoga Instruction', 'Health & Wellness', 64),
+    ('Meditation Guidance', 'Health & Wellness', 65),
+    ('Physical Therapy', 'Health & Wellness', 66),
+    ('Life Coaching', 'Health & Wellness', 67),
+    ('Wellness Coaching', 'Health & Wellness', 68),
+    ('Stress Management', 'Health & Wellness', 69),
+    ('Sleep Optimization', 'Health & Wellness', 70),
+    ('Diet Planning', 'Health & Wellness', 71),
+    ('Exercise Programs', 'Health & Wellness', 72),
+    ('Mindfulness Training', 'Health & Wellness', 73),
+    ('Addiction Recovery', 'Health & Wellness', 74),
+    ('Health Education', 'Health & Wellness', 75),
+    ('Home Improvement', 'Home & Lifestyle', 76),
+    ('Interior Design', 'Home & Lifestyle', 77),
+    ('Gardening', 'Home & Lifestyle', 78),
+    ('Cooking', 'Home & Lifestyle', 79),
+    ('Cleaning Services', 'Home & Lifestyle', 80),
+    ('Handyman Services', 'Home & Lifestyle', 81),
+    ('Electrical Work', 'Home & Lifestyle', 82),
+    ('Plumbing', 'Home & Lifestyle', 83),
+    ('Carpentry', 'Home & Lifestyle', 84),
+    ('Painting', 'Home & Lifestyle', 85),
+    ('Landscaping', 'Home & Lifestyle', 86),
+    ('Pet Care', 'Home & Lifestyle', 87),
+    ('Childcare', 'Home & Lifestyle', 88),
+    ('Elder Care', 'Home & Lifestyle', 89),
+    ('Event Planning', 'Home & Lifestyle', 90),
+    ('Driving Lessons', 'Transportation & Logistics', 91),
+    ('Vehicle Maintenance', 'Transportation & Logistics', 92),
+    ('Moving Services', 'Transportation & Logistics', 93),
+    ('Delivery Services', 'Transportation & Logistics', 94),
+    ('Travel Planning', 'Transportation & Logistics', 95),
+    ('Logistics Coordination', 'Transportation & Logistics', 96),
+    ('Transportation Services', 'Transportation & Logistics', 97),
+    ('Car Repair', 'Transportation & Logistics', 98),
+    ('Bike Maintenance', 'Transportation & Logistics', 99),
+    ('Navigation Help', 'Transportation & Logistics', 100),
+    ('Music Lessons', 'Arts & Crafts', 101),
+    ('Art Classes', 'Arts & Crafts', 102),
+    ('Crafting', 'Arts & Crafts', 103),
+    ('Jewelry Making', 'Arts & Crafts', 104),
+    ('Pottery', 'Arts & Crafts', 105),
+    ('Woodworking', 'Arts & Crafts', 106),
+    ('Sewing', 'Arts & Crafts', 107),
+    ('Knitting', 'Arts & Crafts', 108),
+    ('Embroidery', 'Arts & Crafts', 109),
+    ('Painting Classes', 'Arts & Crafts', 110),
+    ('Dance Lessons', 'Arts & Crafts', 111),
+    ('Theater Arts', 'Arts & Crafts', 112),
+    ('Creative Writing', 'Arts & Crafts', 113),
+    ('Poetry', 'Arts & Crafts', 114),
+    ('Storytelling', 'Arts & Crafts', 115),
+    ('Translation Services', 'Language & Communication', 116),
+    ('Interpretation', 'Language & Communication', 117),
+    ('Public Speaking', 'Language & Communication', 118),
+    ('Communication Skills', 'Language & Communication', 119),
+    ('Presentation Design', 'Language & Communication', 120),
+    ('Technical Writing', 'Language & Communication', 121),
+    ('Proofreading', 'Language & Communication', 122),
+    ('Editing Services', 'Language & Communication', 123),
+    ('Voice Training', 'Language & Communication', 124),
+    ('Interview Preparation', 'Language & Communication', 125),
+    ('Legal Advice', 'Legal & Administrative', 126),
+    ('Document Preparation', 'Legal & Administrative', 127),
+    ('Notary Services', 'Legal & Administrative', 128),
+    ('Immigration Help', 'Legal & Administrative', 129),
+    ('Contract Review', 'Legal & Administrative', 130),
+    ('Administrative Support', 'Legal & Administrative', 131),
+    ('Data Entry', 'Legal & Administrative', 132),
+    ('Virtual Assistant', 'Legal & Administrative', 133),
+    ('Research Services', 'Legal & Administrative', 134),
+    ('Government Forms', 'Legal & Administrative', 135),
+    ('Event Photography', 'Miscellaneous', 136),
+    ('Wedding Planning', 'Miscellaneous', 137),
+    ('Relationship Counseling', 'Miscellaneous', 138),
+    ('Spiritual Guidance', 'Miscellaneous', 139),
+    ('Community Organizing', 'Miscellaneous', 140),
+    ('Volunteer Coordination', 'Miscellaneous', 141),
+    ('Fundraising', 'Miscellaneous', 142),
+    ('Grant Writing', 'Miscellaneous', 143),
+    ('Non-profit Management', 'Miscellaneous', 144),
+    ('Other', 'Miscellaneous', 145);
@@ -0,0 +1,20 @@
+-- Track when posts are created and last edited
+-- Existing posts have no record of either, so both start at the author's signup time
+
+ALTER TABLE posts ADD COLUMN created_at TIMESTAMP WITH TIME ZONE;
+ALTER TABLE posts ADD COLUMN updated_at TIMESTAMP WITH TIME ZONE;
+
+UPDATE posts p
+SET created_at = COALESCE(u.created_at, NOW()),
+    updated_at = COALESCE(u.created_at, NOW())
+FROM users u
+WHERE p.user_id = u.id;
+
+ALTER TABLE posts ALTER COLUMN created_at SET DEFAULT NOW();
+ALTER TABLE posts ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE posts ALTER COLUMN updated_at SET DEFAULT NOW();
+ALTER TABLE posts ALTER COLUMN updated_at SET NOT NULL;
+
+-- Indexes backing the newest/oldest and recently-updated sort orders
+CREATE INDEX idx_posts_created_at ON posts(created_at, id);
+CREATE INDEX idx_posts_updated_at ON posts(updated_at, id);
@@ -0,0 +1,7 @@
+-- Lifecycle state for posts, replacing the old `completed` flag
+-- 'open' posts show up in the community feed; the others are kept for their owner's history
+
+ALTER TABLE posts ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'open'
+    CHECK (status IN ('open', 'in_progress', 'fulfilled', 'closed'));
+
+CREATE INDEX idx_posts_status ON posts(status);
@@ -0,0 +1,12 @@
+-- Posts can expire; a background sweeper moves expired posts to the 'expired' status
+-- Existing posts are left without an expiry: their timestamps were backfilled, so they say
+-- nothing about a post's real age. Authors can still give them one by renewing.
+
+ALTER TABLE posts ADD COLUMN expires_at TIMESTAMP WITH TIME ZONE;
+
+ALTER TABLE posts DROP CONSTRAINT posts_status_check;
+ALTER TABLE posts ADD CONSTRAINT posts_status_check
+    CHECK (status IN ('open', 'in_progress', 'fulfilled', 'closed', 'expired'));
+
+-- Only live posts are ever swept
+CREATE INDEX idx_posts_expires_at ON posts(expires_at) WHERE status IN ('open', 'in_progress');
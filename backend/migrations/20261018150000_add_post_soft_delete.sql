@@ -0,0 +1,6 @@
+-- Soft delete: deleting a post moves it to the owner's trash until it is restored or purged
+
+ALTER TABLE posts ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+
+-- Trash listing and the purge job only ever look at deleted rows
+CREATE INDEX idx_posts_deleted_at ON posts(user_id, deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,18 @@
+-- Every edit to a post keeps a copy of the version it replaced, for moderation disputes
+
+CREATE TABLE post_revisions (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    categories TEXT[] NOT NULL,
+    post_type VARCHAR(20) NOT NULL,
+    pin_code VARCHAR(10),
+    edited_at TIMESTAMP WITH TIME ZONE NOT NULL, -- When this version was written
+    replaced_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    replaced_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    UNIQUE (post_id, revision)
+);
+
+-- Moderators may read any post's history
+ALTER TABLE users ADD COLUMN is_moderator BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- Version counter for optimistic concurrency; exposed to clients as the post's ETag
+-- and bumped by every write to a post
+
+ALTER TABLE posts ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
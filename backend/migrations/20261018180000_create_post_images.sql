@@ -0,0 +1,11 @@
+-- Photos of past work attached to a post, shown in the order the author chose
+
+CREATE TABLE post_images (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    public_id TEXT NOT NULL, -- Cloudinary asset id, needed to delete the upload later
+    position INTEGER NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (post_id, position) DEFERRABLE INITIALLY DEFERRED -- Lets a reorder swap positions within one transaction
+);
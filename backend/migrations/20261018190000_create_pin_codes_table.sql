@@ -0,0 +1,13 @@
+-- Offline gazetteer of Indian pin codes, filled by `backend import-pin-codes <csv>`
+-- Coordinates are the centroid of the pin's post offices and may be missing in the source data
+
+CREATE TABLE pin_codes (
+    pin_code VARCHAR(6) PRIMARY KEY,
+    locality TEXT NOT NULL,
+    district TEXT NOT NULL,
+    state TEXT NOT NULL,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION
+);
+
+CREATE INDEX idx_pin_codes_lat_lon ON pin_codes(latitude, longitude) WHERE latitude IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Pin codes are now stored without whitespace so they can be joined against the gazetteer
+
+UPDATE posts SET pin_code = NULLIF(regexp_replace(pin_code, '\s', '', 'g'), '') WHERE pin_code IS NOT NULL;
+UPDATE users SET pin_code = NULLIF(regexp_replace(pin_code, '\s', '', 'g'), '') WHERE pin_code IS NOT NULL;
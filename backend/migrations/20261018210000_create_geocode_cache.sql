@@ -0,0 +1,8 @@
+-- Responses from the external geocoder, kept so each pin or point is looked up once per TTL
+-- A NULL result records that the provider found nothing, which is worth caching too
+
+CREATE TABLE geocode_cache (
+    cache_key TEXT PRIMARY KEY, -- "pin:110001" or "reverse:28.6315,77.2167"
+    result JSONB,
+    fetched_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,40 @@
+-- A learner asks for a session on someone's offer; the two sides trade time slots until one is accepted
+-- 'requested' waits on the teacher, 'proposed' waits on the learner
+-- Bookings outlive their offer: purging a trashed post only detaches them, keeping the history
+-- (and any reviews) for both sides
+
+CREATE TABLE bookings (
+    id SERIAL PRIMARY KEY,
+    post_id INTEGER REFERENCES posts(id) ON DELETE SET NULL, -- NULL once the offer is purged
+    post_description TEXT NOT NULL, -- Copy of the offer, shown after it is gone
+    learner_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    teacher_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE, -- Owner of the offer
+    message TEXT NOT NULL DEFAULT '',
+    status VARCHAR(20) NOT NULL DEFAULT 'requested'
+        CHECK (status IN ('requested', 'proposed', 'accepted', 'declined', 'cancelled', 'completed')),
+    starts_at TIMESTAMP WITH TIME ZONE, -- Copied from the accepted slot
+    ends_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (learner_id <> teacher_id),
+    CHECK (status NOT IN ('accepted', 'completed') OR (starts_at IS NOT NULL AND ends_at IS NOT NULL))
+);
+
+-- One open negotiation or session per learner and offer at a time
+CREATE UNIQUE INDEX idx_bookings_active ON bookings(post_id, learner_id)
+    WHERE status IN ('requested', 'proposed', 'accepted');
+CREATE INDEX idx_bookings_learner_id ON bookings(learner_id);
+CREATE INDEX idx_bookings_teacher_id ON bookings(teacher_id);
+
+CREATE TABLE booking_slots (
+    id SERIAL PRIMARY KEY,
+    booking_id INTEGER NOT NULL REFERENCES bookings(id) ON DELETE CASCADE,
+    proposed_by INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    starts_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    ends_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    superseded BOOLEAN NOT NULL DEFAULT FALSE, -- Set when the other side answers with new times
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX idx_booking_slots_booking_id ON booking_slots(booking_id);
@@ -0,0 +1,69 @@
+-- Time bank: hour credits move between accounts through a double-entry ledger.
+-- Every transaction's entries sum to zero; the account balance is kept alongside for locking.
+-- Amounts are stored in minutes so sessions of any length settle exactly.
+
+CREATE TABLE credit_accounts (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER UNIQUE REFERENCES users(id) ON DELETE CASCADE, -- NULL for the bank's issuing account
+    balance_minutes INTEGER NOT NULL DEFAULT 0,
+    CHECK (user_id IS NULL OR balance_minutes >= 0) -- Users can never overdraw
+);
+
+CREATE UNIQUE INDEX idx_credit_accounts_bank ON credit_accounts((user_id IS NULL)) WHERE user_id IS NULL;
+
+CREATE TABLE credit_transactions (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('opening', 'exchange')),
+    booking_id INTEGER UNIQUE REFERENCES bookings(id) ON DELETE SET NULL, -- A booking settles at most once
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE ledger_entries (
+    id SERIAL PRIMARY KEY,
+    transaction_id INTEGER NOT NULL REFERENCES credit_transactions(id) ON DELETE CASCADE,
+    account_id INTEGER NOT NULL REFERENCES credit_accounts(id) ON DELETE CASCADE,
+    amount_minutes INTEGER NOT NULL CHECK (amount_minutes <> 0), -- Positive credits the account
+    UNIQUE (transaction_id, account_id)
+);
+
+CREATE INDEX idx_ledger_entries_account_id ON ledger_entries(account_id);
+
+-- Checked at commit, once all of a transaction's entries are in
+CREATE FUNCTION check_ledger_transaction_balanced() RETURNS trigger AS $$
+BEGIN
+    IF (SELECT SUM(amount_minutes) FROM ledger_entries WHERE transaction_id = NEW.transaction_id) <> 0 THEN
+        RAISE EXCEPTION 'Ledger transaction % does not balance', NEW.transaction_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER ledger_entries_balanced
+    AFTER INSERT OR UPDATE ON ledger_entries
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION check_ledger_transaction_balanced();
+
+INSERT INTO credit_accounts (user_id) VALUES (NULL);
+
+-- Existing users get the default starting balance (TIME_BANK_STARTING_HOURS only applies to new sign-ups)
+DO $$
+DECLARE
+    bank INTEGER;
+    account RECORD;
+    txn INTEGER;
+BEGIN
+    SELECT id INTO bank FROM credit_accounts WHERE user_id IS NULL;
+
+    FOR account IN INSERT INTO credit_accounts (user_id, balance_minutes) SELECT id, 120 FROM users RETURNING id LOOP
+        INSERT INTO credit_transactions (kind) VALUES ('opening') RETURNING id INTO txn;
+        INSERT INTO ledger_entries (transaction_id, account_id, amount_minutes)
+            VALUES (txn, account.id, 120), (txn, bank, -120);
+        UPDATE credit_accounts SET balance_minutes = balance_minutes - 120 WHERE id = bank;
+    END LOOP;
+END;
+$$;
+
+-- A session only settles once both participants have confirmed it took place
+ALTER TABLE bookings
+    ADD COLUMN learner_confirmed_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN teacher_confirmed_at TIMESTAMP WITH TIME ZONE;
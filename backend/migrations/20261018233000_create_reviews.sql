@@ -0,0 +1,15 @@
+-- After a completed booking each participant may rate the other once
+
+CREATE TABLE reviews (
+    id SERIAL PRIMARY KEY,
+    booking_id INTEGER NOT NULL REFERENCES bookings(id) ON DELETE CASCADE,
+    reviewer_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reviewee_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    text TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (booking_id, reviewer_id),
+    CHECK (reviewer_id <> reviewee_id)
+);
+
+CREATE INDEX idx_reviews_reviewee_id ON reviews(reviewee_id, created_at DESC);
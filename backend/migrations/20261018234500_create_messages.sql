@@ -0,0 +1,56 @@
+-- Private conversations between two users, optionally about one of their posts.
+-- The pair is stored lowest id first so each pair and post has a single conversation.
+
+CREATE TABLE conversations (
+    id SERIAL PRIMARY KEY,
+    user_a_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_b_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    post_id INTEGER REFERENCES posts(id) ON DELETE SET NULL, -- NULL for a general conversation
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_message_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (user_a_id < user_b_id)
+);
+
+CREATE UNIQUE INDEX idx_conversations_pair ON conversations(user_a_id, user_b_id, post_id) NULLS NOT DISTINCT;
+CREATE INDEX idx_conversations_user_b_id ON conversations(user_b_id);
+
+CREATE TABLE messages (
+    id SERIAL PRIMARY KEY,
+    conversation_id INTEGER NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    sender_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    read_at TIMESTAMP WITH TIME ZONE -- Set when the recipient marks the conversation read
+);
+
+CREATE INDEX idx_messages_conversation_id ON messages(conversation_id, id);
+CREATE INDEX idx_messages_unread ON messages(conversation_id) WHERE read_at IS NULL;
+
+-- Purging a post must not cost either participant their messages: conversations about it
+-- become the pair's general conversation, or are folded into it when one already exists
+CREATE FUNCTION detach_post_conversations() RETURNS trigger AS $$
+DECLARE
+    conversation RECORD;
+    general INTEGER;
+BEGIN
+    FOR conversation IN SELECT * FROM conversations WHERE post_id = OLD.id FOR UPDATE LOOP
+        SELECT id INTO general FROM conversations
+            WHERE user_a_id = conversation.user_a_id AND user_b_id = conversation.user_b_id AND post_id IS NULL
+            FOR UPDATE;
+
+        IF general IS NULL THEN
+            UPDATE conversations SET post_id = NULL WHERE id = conversation.id;
+        ELSE
+            UPDATE messages SET conversation_id = general WHERE conversation_id = conversation.id;
+            UPDATE conversations SET last_message_at = GREATEST(last_message_at, conversation.last_message_at)
+                WHERE id = general;
+            DELETE FROM conversations WHERE id = conversation.id;
+        END IF;
+    END LOOP;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER posts_detach_conversations
+    BEFORE DELETE ON posts
+    FOR EACH ROW EXECUTE FUNCTION detach_post_conversations();
@@ -0,0 +1,32 @@
+#!/bin/sh
+# Loads the All India Pincode Directory into the pin_codes gazetteer.
+#
+# Usage: scripts/import_pin_codes.sh <csv-url-or-file>
+#
+# India Post publishes the directory on the Open Government Data platform (data.gov.in) as
+# "All India Pincode Directory". Pass the CSV download link from the dataset page, or a copy
+# you have already downloaded. Re-running it updates existing pins in place.
+# DATABASE_URL must point at the database to fill.
+set -eu
+
+if [ $# -ne 1 ]; then
+    echo "Usage: $0 <csv-url-or-file>" >&2
+    exit 1
+fi
+
+source=$1
+cd "$(dirname "$0")/.."
+
+case $source in
+    http://* | https://*)
+        file=$(mktemp --suffix=.csv)
+        trap 'rm -f "$file"' EXIT
+        echo "Downloading $source"
+        curl --fail --location --silent --show-error --output "$file" "$source"
+        ;;
+    *)
+        file=$source
+        ;;
+esac
+
+cargo run --release -- import-pin-codes "$file"
@@ -1,6 +1,11 @@
-use crate::cloudinary::{CloudinaryConfig, CloudinaryService};
+use crate::cloudinary::{CloudinaryConfig, CloudinaryService, ImageFolder};
 use crate::error::AppError;
+use crate::follows::follow_counts;
+use crate::gazetteer::{normalize_pin_code, pin_code_problem, pin_location};
+use crate::posts::user_post_counts;
+use crate::reviews::user_reputation;
 use crate::structs::{AuthResponse, LoginRequest, NewUser, ProfilePictureUpdate, UserProfile};
+use crate::timebank::open_account;
 use axum::{Form, Json, extract::{Path, State}};
 use bcrypt::{DEFAULT_COST, hash, verify};
 use http::StatusCode;
@@ -28,14 +33,26 @@ pub async fn register(
         }));
     }
 
-    if let Some(ref name) = new_user.name {
-        if name.trim().is_empty() {
-            return Ok(Json(AuthResponse {
-                success: false,
-                message: "Name cannot be empty".to_string(),
-                user_id: None,
-            }));
-        }
+    if let Some(ref name) = new_user.name
+        && name.trim().is_empty()
+    {
+        return Ok(Json(AuthResponse {
+            success: false,
+            message: "Name cannot be empty".to_string(),
+            user_id: None,
+        }));
+    }
+
+    let pin_code = new_user.pin_code.as_deref().and_then(normalize_pin_code);
+
+    if let Some(ref pin) = pin_code
+        && let Some(problem) = pin_code_problem(&pool, pin).await?
+    {
+        return Ok(Json(AuthResponse {
+            success: false,
+            message: format!("Invalid fields: pin_code: {}", problem),
+            user_id: None,
+        }));
     }
 
     let existing_user = sqlx::query!("SELECT id FROM users WHERE email = $1", new_user.email)
@@ -64,25 +81,34 @@ pub async fn register(
 
         let temp_public_id = format!("profile_pictures/temp_{}", uuid::Uuid::new_v4());
         let image_url = cloudinary
-            .upload_image(profile_picture_data, Some(temp_public_id))
+            .upload_image(
+                profile_picture_data,
+                ImageFolder::ProfilePictures,
+                Some(temp_public_id),
+            )
             .await
             .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
-        Some(image_url)
+        Some(image_url.url)
     } else {
         None
     };
 
+    let mut tx = pool.begin().await?;
+
     let user = sqlx::query!(
         "INSERT INTO users (email, password_hash, name, pin_code, profile_picture) VALUES ($1, $2, $3, $4, $5) RETURNING id",
         new_user.email,
         password_hash,
         new_user.name,
-        new_user.pin_code,
+        pin_code,
         profile_picture_url
     )
-    .fetch_one(&pool)
+    .fetch_one(&mut *tx)
     .await?;
 
+    open_account(&mut tx, user.id).await?;
+    tx.commit().await?;
+
     session.insert("user_id", user.id).await.map_err(|_| {
         AppError::HttpError(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -201,18 +227,31 @@ pub async fn get_my_profile(
     let user_id = get_my_user_id(session).await?.0;
 
     let user = sqlx::query!(
-        "SELECT id, email, name, pin_code, profile_picture FROM users WHERE id = $1",
+        r#"SELECT u.id, u.email, u.name, u.pin_code, u.profile_picture,
+                loc.locality as "locality?", loc.district as "district?", loc.state as "state?"
+         FROM users u LEFT JOIN pin_codes loc ON loc.pin_code = u.pin_code
+         WHERE u.id = $1"#,
         user_id
     )
     .fetch_one(&pool)
     .await?;
 
+    let (rating, reviews) = user_reputation(&pool, user.id).await?;
+    let (follower_count, following_count) = follow_counts(&pool, user.id).await?;
+    let post_counts = user_post_counts(&pool, user.id).await?;
+
     Ok(Json(UserProfile {
         id: user.id,
         email: user.email,
         name: user.name,
         pin_code: user.pin_code,
+        location: pin_location(user.locality, user.district, user.state),
         profile_picture: user.profile_picture,
+        rating,
+        reviews,
+        follower_count,
+        following_count,
+        post_counts,
     }))
 }
 
@@ -221,18 +260,31 @@ pub async fn get_user_profile(
     Path(user_id): Path<i32>,
 ) -> Result<Json<UserProfile>, AppError> {
     let user = sqlx::query!(
-        "SELECT id, email, name, pin_code, profile_picture FROM users WHERE id = $1",
+        r#"SELECT u.id, u.email, u.name, u.pin_code, u.profile_picture,
+                loc.locality as "locality?", loc.district as "district?", loc.state as "state?"
+         FROM users u LEFT JOIN pin_codes loc ON loc.pin_code = u.pin_code
+         WHERE u.id = $1"#,
         user_id
     )
     .fetch_one(&pool)
     .await?;
 
+    let (rating, reviews) = user_reputation(&pool, user.id).await?;
+    let (follower_count, following_count) = follow_counts(&pool, user.id).await?;
+    let post_counts = user_post_counts(&pool, user.id).await?;
+
     Ok(Json(UserProfile {
         id: user.id,
         email: user.email,
         name: user.name,
         pin_code: user.pin_code,
+        location: pin_location(user.locality, user.district, user.state),
         profile_picture: user.profile_picture,
+        rating,
+        reviews,
+        follower_count,
+        following_count,
+        post_counts,
     }))
 }
 
@@ -250,7 +302,11 @@ pub async fn update_profile_picture(
     let public_id = format!("profile_pictures/user_{}", user_id);
 
     let image_url = cloudinary
-        .upload_image(&update.profile_picture, Some(public_id))
+        .upload_image(
+            &update.profile_picture,
+            ImageFolder::ProfilePictures,
+            Some(public_id),
+        )
         .await
         .map_err(|e| {
             tracing::error!("Cloudinary upload failed: {}", e);
@@ -258,18 +314,33 @@ pub async fn update_profile_picture(
         })?;
 
     let user = sqlx::query!(
-        "UPDATE users SET profile_picture = $1 WHERE id = $2 RETURNING id, email, name, pin_code, profile_picture",
-        image_url,
+        r#"WITH updated AS (
+             UPDATE users SET profile_picture = $1 WHERE id = $2 RETURNING id, email, name, pin_code, profile_picture
+         )
+         SELECT u.id as "id!", u.email as "email!", u.name, u.pin_code, u.profile_picture,
+                loc.locality as "locality?", loc.district as "district?", loc.state as "state?"
+         FROM updated u LEFT JOIN pin_codes loc ON loc.pin_code = u.pin_code"#,
+        image_url.url,
         user_id
     )
     .fetch_one(&pool)
     .await?;
 
+    let (rating, reviews) = user_reputation(&pool, user.id).await?;
+    let (follower_count, following_count) = follow_counts(&pool, user.id).await?;
+    let post_counts = user_post_counts(&pool, user.id).await?;
+
     Ok(Json(UserProfile {
         id: user.id,
         email: user.email,
         name: user.name,
         pin_code: user.pin_code,
+        location: pin_location(user.locality, user.district, user.state),
         profile_picture: user.profile_picture,
+        rating,
+        reviews,
+        follower_count,
+        following_count,
+        post_counts,
     }))
 }
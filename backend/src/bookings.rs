@@ -0,0 +1,648 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::pagination::encode_cursor;
+use crate::structs::{
+    Booking, BookingQuery, BookingRole, BookingScope, BookingSlot, BookingStatus, NewBooking, Page,
+    PageParams, PostStatus, PostType, SlotChoice, SlotProposal, TimeSlot,
+};
+use crate::timebank::{check_affordable, settle_booking};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use http::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction, types::Json as JsonColumn};
+use tower_sessions::Session;
+
+const MAX_SLOTS: usize = 5;
+const MAX_SESSION_HOURS: i64 = 8;
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+struct BookingRow {
+    id: i32,
+    post_id: Option<i32>,
+    post_description: String,
+    learner_id: i32,
+    learner_name: Option<String>,
+    teacher_id: i32,
+    teacher_name: Option<String>,
+    message: String,
+    status: String,
+    starts_at: Option<chrono::DateTime<chrono::Utc>>,
+    ends_at: Option<chrono::DateTime<chrono::Utc>>,
+    slots: JsonColumn<Vec<BookingSlot>>,
+    learner_confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    teacher_confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    sort_time: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<BookingRow> for Booking {
+    fn from(row: BookingRow) -> Self {
+        Booking {
+            id: row.id,
+            post_id: row.post_id,
+            post_description: row.post_description,
+            learner_id: row.learner_id,
+            learner_name: row.learner_name,
+            teacher_id: row.teacher_id,
+            teacher_name: row.teacher_name,
+            message: row.message,
+            status: BookingStatus::from(row.status.as_str()),
+            starts_at: row.starts_at,
+            ends_at: row.ends_at,
+            slots: row.slots.0,
+            learner_confirmed_at: row.learner_confirmed_at,
+            teacher_confirmed_at: row.teacher_confirmed_at,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+impl BookingStatus {
+    // Requested and proposed bounce between the two sides until a slot is accepted
+    fn can_become(self, next: BookingStatus) -> bool {
+        use BookingStatus::*;
+
+        matches!(
+            (self, next),
+            (Requested, Proposed | Accepted | Declined | Cancelled)
+                | (Proposed, Requested | Accepted | Declined | Cancelled)
+                | (Accepted, Completed | Cancelled)
+        )
+    }
+}
+
+// The participant row locked for the rest of a transition's transaction
+struct LockedBooking {
+    status: BookingStatus,
+    learner_id: i32,
+    teacher_id: i32,
+    starts_at: Option<chrono::DateTime<chrono::Utc>>,
+    ends_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl LockedBooking {
+    // Whose answer the negotiation is waiting for
+    fn awaiting(&self) -> Option<i32> {
+        match self.status {
+            BookingStatus::Requested => Some(self.teacher_id),
+            BookingStatus::Proposed => Some(self.learner_id),
+            _ => None,
+        }
+    }
+
+    fn check_transition(&self, next: BookingStatus) -> Result<(), AppError> {
+        if self.status.can_become(next) {
+            Ok(())
+        } else {
+            Err(AppError::HttpError(
+                StatusCode::CONFLICT,
+                anyhow::anyhow!(
+                    "Cannot change booking status from {} to {}",
+                    self.status,
+                    next
+                ),
+            ))
+        }
+    }
+
+    fn check_turn(&self, user_id: i32) -> Result<(), AppError> {
+        if self.awaiting() == Some(user_id) {
+            Ok(())
+        } else {
+            Err(AppError::HttpError(
+                StatusCode::CONFLICT,
+                anyhow::anyhow!("This booking is waiting on the other participant"),
+            ))
+        }
+    }
+}
+
+// Only the learner and the teacher can see or act on a booking; to anyone else it does not exist
+async fn lock_booking(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    user_id: i32,
+) -> Result<LockedBooking, AppError> {
+    let booking = sqlx::query!(
+        "SELECT status, learner_id, teacher_id, starts_at, ends_at FROM bookings
+         WHERE id = $1 AND (learner_id = $2 OR teacher_id = $2) FOR UPDATE",
+        id,
+        user_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| booking_not_found(id))?;
+
+    Ok(LockedBooking {
+        status: BookingStatus::from(booking.status.as_str()),
+        learner_id: booking.learner_id,
+        teacher_id: booking.teacher_id,
+        starts_at: booking.starts_at,
+        ends_at: booking.ends_at,
+    })
+}
+
+fn booking_not_found(id: i32) -> AppError {
+    AppError::HttpError(
+        StatusCode::NOT_FOUND,
+        anyhow::anyhow!("Booking with id {} not found.", id),
+    )
+}
+
+fn validate_slots(slots: &[TimeSlot]) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    if slots.is_empty() || slots.len() > MAX_SLOTS {
+        errors.push(format!(
+            "slots: propose between 1 and {} time slots",
+            MAX_SLOTS
+        ));
+    }
+
+    let now = chrono::Utc::now();
+    for (i, slot) in slots.iter().enumerate() {
+        if slot.starts_at <= now {
+            errors.push(format!("slots[{}]: must start in the future", i));
+        }
+        if slot.ends_at <= slot.starts_at {
+            errors.push(format!("slots[{}]: must end after it starts", i));
+        } else if slot.ends_at - slot.starts_at > chrono::Duration::hours(MAX_SESSION_HOURS) {
+            errors.push(format!(
+                "slots[{}]: sessions can last at most {} hours",
+                i, MAX_SESSION_HOURS
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Invalid fields: {}", errors.join("; ")),
+        ))
+    }
+}
+
+// Replaces the open proposal with the given times from `user_id`
+async fn propose_slots(
+    tx: &mut Transaction<'_, Postgres>,
+    booking_id: i32,
+    user_id: i32,
+    slots: &[TimeSlot],
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "UPDATE booking_slots SET superseded = TRUE WHERE booking_id = $1 AND NOT superseded",
+        booking_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    let starts: Vec<_> = slots.iter().map(|s| s.starts_at).collect();
+    let ends: Vec<_> = slots.iter().map(|s| s.ends_at).collect();
+
+    sqlx::query!(
+        "INSERT INTO booking_slots (booking_id, proposed_by, starts_at, ends_at)
+         SELECT $1, $2, * FROM unnest($3::TIMESTAMPTZ[], $4::TIMESTAMPTZ[])",
+        booking_id,
+        user_id,
+        &starts,
+        &ends
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn fetch_booking(pool: &PgPool, id: i32, user_id: i32) -> Result<Booking, AppError> {
+    let row = sqlx::query_as!(
+        BookingRow,
+        r#"SELECT b.id, b.post_id, COALESCE(p.description, b.post_description) as "post_description!", b.learner_id, l.name as "learner_name?", b.teacher_id, t.name as "teacher_name?",
+                b.message, b.status, b.starts_at, b.ends_at, b.learner_confirmed_at, b.teacher_confirmed_at, b.created_at, b.updated_at, b.created_at as "sort_time!",
+                COALESCE((SELECT json_agg(json_build_object('id', s.id, 'proposed_by', s.proposed_by, 'starts_at', s.starts_at, 'ends_at', s.ends_at) ORDER BY s.starts_at)
+                          FROM booking_slots s WHERE s.booking_id = b.id AND NOT s.superseded), '[]') as "slots!: JsonColumn<Vec<BookingSlot>>"
+         FROM bookings b
+         LEFT JOIN posts p ON p.id = b.post_id
+         LEFT JOIN users l ON l.id = b.learner_id
+         LEFT JOIN users t ON t.id = b.teacher_id
+         WHERE b.id = $1 AND (b.learner_id = $2 OR b.teacher_id = $2)"#,
+        id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(Booking::from).ok_or_else(|| booking_not_found(id))
+}
+
+pub async fn get_booking(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+// Bookings the caller takes part in, on either side unless `role` narrows it.
+// Upcoming ones are ordered by their session time (or earliest proposed slot), soonest first.
+pub async fn list_bookings(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(query): Query<BookingQuery>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Booking>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let limit = params.limit();
+    let (after_time, after_id) = params
+        .after::<(chrono::DateTime<chrono::Utc>, i32)>()?
+        .unzip();
+    let upcoming = query.scope == BookingScope::Upcoming;
+    let role = query.role.map(|r| match r {
+        BookingRole::Learner => "learner",
+        BookingRole::Teacher => "teacher",
+    });
+
+    let rows = sqlx::query_as!(
+        BookingRow,
+        r#"SELECT b.id, b.post_id, COALESCE(p.description, b.post_description) as "post_description!", b.learner_id, l.name as "learner_name?", b.teacher_id, t.name as "teacher_name?",
+                b.message, b.status, b.starts_at, b.ends_at, b.learner_confirmed_at, b.teacher_confirmed_at, b.created_at, b.updated_at, k.sort_time as "sort_time!",
+                COALESCE((SELECT json_agg(json_build_object('id', s.id, 'proposed_by', s.proposed_by, 'starts_at', s.starts_at, 'ends_at', s.ends_at) ORDER BY s.starts_at)
+                          FROM booking_slots s WHERE s.booking_id = b.id AND NOT s.superseded), '[]') as "slots!: JsonColumn<Vec<BookingSlot>>"
+         FROM bookings b
+         LEFT JOIN posts p ON p.id = b.post_id
+         LEFT JOIN users l ON l.id = b.learner_id
+         LEFT JOIN users t ON t.id = b.teacher_id
+         CROSS JOIN LATERAL (SELECT CASE WHEN $2
+                THEN COALESCE(b.starts_at, (SELECT MIN(s.starts_at) FROM booking_slots s WHERE s.booking_id = b.id AND NOT s.superseded), b.created_at)
+                ELSE COALESCE(b.ends_at, b.updated_at) END AS sort_time) k
+         WHERE (b.learner_id = $1 OR b.teacher_id = $1)
+           AND ($3::TEXT IS NULL OR ($3 = 'learner' AND b.learner_id = $1) OR ($3 = 'teacher' AND b.teacher_id = $1))
+           AND (b.status IN ('requested', 'proposed') OR (b.status = 'accepted' AND b.ends_at > NOW())) = $2
+           AND ($4::TIMESTAMPTZ IS NULL OR CASE WHEN $2
+                THEN (k.sort_time, b.id) > ($4, $5::INT4)
+                ELSE (k.sort_time, b.id) < ($4, $5::INT4) END)
+         ORDER BY
+           CASE WHEN $2 THEN k.sort_time END ASC,
+           CASE WHEN $2 THEN b.id END ASC,
+           k.sort_time DESC,
+           b.id DESC
+         LIMIT $6"#,
+        user_id,
+        upcoming,
+        role,
+        after_time,
+        after_id,
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let page = Page::from_rows(rows, limit, |row| encode_cursor(&(row.sort_time, row.id)));
+
+    Ok(Json(Page {
+        items: page.items.into_iter().map(Booking::from).collect(),
+        next_cursor: page.next_cursor,
+    }))
+}
+
+// The learner opens the negotiation with a message and a few times that suit them
+pub async fn create_booking(
+    State(pool): State<PgPool>,
+    session: Session,
+    Json(new_booking): Json<NewBooking>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    validate_slots(&new_booking.slots)?;
+    if new_booking.message.len() > MAX_MESSAGE_LENGTH {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!(
+                "Invalid fields: message: at most {} characters",
+                MAX_MESSAGE_LENGTH
+            ),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let post = sqlx::query!(
+        "SELECT user_id, post_type, status, description FROM posts WHERE id = $1 AND deleted_at IS NULL FOR SHARE",
+        new_booking.post_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found.", new_booking.post_id),
+        )
+    })?;
+
+    if PostType::from(post.post_type.as_str()) != PostType::Offer {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Sessions can only be booked on offers"),
+        ));
+    }
+    if post.user_id == user_id {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("You cannot book a session on your own offer"),
+        ));
+    }
+    if PostStatus::from(post.status.as_str()) != PostStatus::Open {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("This offer is no longer open for bookings"),
+        ));
+    }
+
+    let active = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM bookings WHERE post_id = $1 AND learner_id = $2
+                         AND status IN ('requested', 'proposed', 'accepted')) as "exists!""#,
+        new_booking.post_id,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if active {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("You already have an open booking for this offer"),
+        ));
+    }
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO bookings (post_id, post_description, learner_id, teacher_id, message) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        new_booking.post_id,
+        post.description,
+        user_id,
+        post.user_id,
+        new_booking.message.trim()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    propose_slots(&mut tx, id, user_id, &new_booking.slots).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+// Either side answers the other's proposal with new times, handing the turn back
+pub async fn propose_booking_slots(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Json(proposal): Json<SlotProposal>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    validate_slots(&proposal.slots)?;
+
+    let mut tx = pool.begin().await?;
+    let booking = lock_booking(&mut tx, id, user_id).await?;
+
+    let next = if user_id == booking.teacher_id {
+        BookingStatus::Proposed
+    } else {
+        BookingStatus::Requested
+    };
+    booking.check_transition(next)?;
+    booking.check_turn(user_id)?;
+
+    propose_slots(&mut tx, id, user_id, &proposal.slots).await?;
+
+    sqlx::query!(
+        "UPDATE bookings SET status = $1, updated_at = NOW() WHERE id = $2",
+        next.to_string(),
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+// Accepting picks one of the other side's open slots as the session time
+pub async fn accept_booking(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Json(choice): Json<SlotChoice>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut tx = pool.begin().await?;
+    let booking = lock_booking(&mut tx, id, user_id).await?;
+
+    booking.check_transition(BookingStatus::Accepted)?;
+    booking.check_turn(user_id)?;
+
+    let slot = sqlx::query!(
+        "SELECT starts_at, ends_at FROM booking_slots
+         WHERE id = $1 AND booking_id = $2 AND NOT superseded AND proposed_by <> $3",
+        choice.slot_id,
+        id,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!(
+                "Slot {} is not one of the open proposed times",
+                choice.slot_id
+            ),
+        )
+    })?;
+
+    if slot.starts_at <= chrono::Utc::now() {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("That time has already passed; propose new times instead"),
+        ));
+    }
+
+    // Neither participant can be in two accepted sessions at once
+    let clash = sqlx::query_scalar!(
+        "SELECT id FROM bookings
+         WHERE status = 'accepted' AND id <> $1
+           AND (learner_id IN ($2, $3) OR teacher_id IN ($2, $3))
+           AND tstzrange(starts_at, ends_at) && tstzrange($4, $5)
+         LIMIT 1",
+        id,
+        booking.learner_id,
+        booking.teacher_id,
+        slot.starts_at,
+        slot.ends_at
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(other) = clash {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("That time overlaps accepted booking {}", other),
+        ));
+    }
+
+    check_affordable(
+        &mut tx,
+        id,
+        booking.learner_id,
+        slot.starts_at,
+        slot.ends_at,
+    )
+    .await?;
+
+    sqlx::query!(
+        "UPDATE booking_slots SET superseded = TRUE WHERE booking_id = $1 AND NOT superseded",
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE bookings SET status = 'accepted', starts_at = $1, ends_at = $2, updated_at = NOW() WHERE id = $3",
+        slot.starts_at,
+        slot.ends_at,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+pub async fn decline_booking(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut tx = pool.begin().await?;
+    let booking = lock_booking(&mut tx, id, user_id).await?;
+
+    booking.check_transition(BookingStatus::Declined)?;
+    booking.check_turn(user_id)?;
+
+    close_booking(&mut tx, id, BookingStatus::Declined).await?;
+    tx.commit().await?;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+// Either participant can call off a booking until the session has started
+pub async fn cancel_booking(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut tx = pool.begin().await?;
+    let booking = lock_booking(&mut tx, id, user_id).await?;
+
+    booking.check_transition(BookingStatus::Cancelled)?;
+    if booking
+        .starts_at
+        .is_some_and(|starts_at| starts_at <= chrono::Utc::now())
+    {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("The session has already started"),
+        ));
+    }
+
+    close_booking(&mut tx, id, BookingStatus::Cancelled).await?;
+    tx.commit().await?;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+// Each participant confirms an accepted session took place, once its end time has passed.
+// The second confirmation completes it and pays the teacher from the learner's time credits
+// in the same transaction.
+pub async fn complete_booking(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<Json<Booking>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut tx = pool.begin().await?;
+    let booking = lock_booking(&mut tx, id, user_id).await?;
+
+    booking.check_transition(BookingStatus::Completed)?;
+    if booking
+        .ends_at
+        .is_none_or(|ends_at| ends_at > chrono::Utc::now())
+    {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("The session has not ended yet"),
+        ));
+    }
+
+    let confirmed = sqlx::query!(
+        r#"UPDATE bookings SET
+            learner_confirmed_at = CASE WHEN learner_id = $2 THEN COALESCE(learner_confirmed_at, NOW()) ELSE learner_confirmed_at END,
+            teacher_confirmed_at = CASE WHEN teacher_id = $2 THEN COALESCE(teacher_confirmed_at, NOW()) ELSE teacher_confirmed_at END,
+            updated_at = NOW()
+         WHERE id = $1
+         RETURNING learner_confirmed_at IS NOT NULL AND teacher_confirmed_at IS NOT NULL as "both!""#,
+        id,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if confirmed.both {
+        settle_booking(&mut tx, id).await?;
+        close_booking(&mut tx, id, BookingStatus::Completed).await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(fetch_booking(&pool, id, user_id).await?))
+}
+
+async fn close_booking(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    status: BookingStatus,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "UPDATE booking_slots SET superseded = TRUE WHERE booking_id = $1 AND NOT superseded",
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE bookings SET status = $1, updated_at = NOW() WHERE id = $2",
+        status.to_string(),
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::posts::{PostScope, fetch_post_page};
+use crate::structs::{BookmarkResponse, Page, PageParams, Post, PostFilter, SortParams};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use http::StatusCode;
+use sqlx::PgPool;
+use tower_sessions::Session;
+
+pub async fn add_bookmark(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(post_id): Path<i32>,
+) -> Result<Json<BookmarkResponse>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM posts WHERE id = $1 AND deleted_at IS NULL) as "exists!""#,
+        post_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found.", post_id),
+        ));
+    }
+
+    // Bookmarking twice is a no-op
+    sqlx::query!(
+        "INSERT INTO bookmarks (user_id, post_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        user_id,
+        post_id
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(BookmarkResponse {
+        post_id,
+        bookmarked: true,
+    }))
+}
+
+pub async fn remove_bookmark(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(post_id): Path<i32>,
+) -> Result<Json<BookmarkResponse>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    sqlx::query!(
+        "DELETE FROM bookmarks WHERE user_id = $1 AND post_id = $2",
+        user_id,
+        post_id
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(BookmarkResponse {
+        post_id,
+        bookmarked: false,
+    }))
+}
+
+// The session user's bookmarked posts, in the same shape and order as the community listings
+pub async fn list_bookmarks(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let scope = PostScope {
+        bookmarked_by: Some(user_id),
+        viewer_id: Some(user_id),
+        ..Default::default()
+    };
+
+    let page = fetch_post_page(&pool, scope, &PostFilter::default(), sorting.sort, &params).await?;
+
+    Ok(Json(page))
+}
@@ -0,0 +1,35 @@
+use crate::error::AppError;
+use crate::structs::Category;
+use axum::{Json, extract::State};
+use http::StatusCode;
+use sqlx::PgPool;
+
+pub async fn list_categories(State(pool): State<PgPool>) -> Result<Json<Vec<Category>>, AppError> {
+    let categories = sqlx::query_as!(
+        Category,
+        "SELECT name, group_name FROM categories ORDER BY position"
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(categories))
+}
+
+pub async fn validate_categories(pool: &PgPool, categories: &[String]) -> Result<(), AppError> {
+    let unknown = sqlx::query_scalar!(
+        r#"SELECT c as "name!" FROM unnest($1::TEXT[]) c
+         WHERE NOT EXISTS (SELECT 1 FROM categories WHERE name = c)"#,
+        categories
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Unknown categories: {}", unknown.join(", ")),
+        ))
+    }
+}
@@ -24,6 +24,35 @@ impl CloudinaryConfig {
     }
 }
 
+// Each folder has its own crop: avatars are small squares, post photos keep their aspect ratio
+#[derive(Debug, Clone, Copy)]
+pub enum ImageFolder {
+    ProfilePictures,
+    Posts,
+}
+
+impl ImageFolder {
+    fn name(self) -> &'static str {
+        match self {
+            ImageFolder::ProfilePictures => "profile_pictures",
+            ImageFolder::Posts => "posts",
+        }
+    }
+
+    fn transformation(self) -> &'static str {
+        match self {
+            ImageFolder::ProfilePictures => "c_fill,w_300,h_300,f_auto,q_auto",
+            ImageFolder::Posts => "c_limit,w_1200,h_1200,f_auto,q_auto",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadedImage {
+    pub url: String,
+    pub public_id: String,
+}
+
 pub struct CloudinaryService {
     config: CloudinaryConfig,
     client: reqwest::Client,
@@ -37,7 +66,12 @@ impl CloudinaryService {
         }
     }
 
-    pub async fn upload_image(&self, base64_data: &str, public_id: Option<String>) -> Result<String> {
+    pub async fn upload_image(
+        &self,
+        base64_data: &str,
+        folder: ImageFolder,
+        public_id: Option<String>,
+    ) -> Result<UploadedImage> {
         tracing::info!("Cloudinary upload_image called");
         
         // Remove data URL prefix if present
@@ -66,8 +100,8 @@ impl CloudinaryService {
         // Create parameters for signature
         let mut params_for_signature = std::collections::HashMap::new();
         params_for_signature.insert("timestamp", timestamp_str.as_str());
-        params_for_signature.insert("folder", "profile_pictures");
-        params_for_signature.insert("transformation", "c_fill,w_300,h_300,f_auto,q_auto");
+        params_for_signature.insert("folder", folder.name());
+        params_for_signature.insert("transformation", folder.transformation());
         
         if let Some(ref id) = public_id {
             tracing::info!("Using public_id: {}", id);
@@ -82,12 +116,12 @@ impl CloudinaryService {
         // Create multipart form with signed parameters
         tracing::info!("Creating multipart form");
         let mut form = multipart::Form::new()
-            .part("file", multipart::Part::bytes(image_bytes).file_name("profile.jpg"))
+            .part("file", multipart::Part::bytes(image_bytes).file_name("image.jpg"))
             .text("timestamp", timestamp_str)
             .text("api_key", self.config.api_key.clone())
             .text("signature", signature)
-            .text("folder", "profile_pictures")
-            .text("transformation", "c_fill,w_300,h_300,f_auto,q_auto");
+            .text("folder", folder.name())
+            .text("transformation", folder.transformation());
 
         // Add public_id if provided
         if let Some(id) = public_id {
@@ -117,8 +151,13 @@ impl CloudinaryService {
                     anyhow::anyhow!("No secure_url in Cloudinary response")
                 })?;
 
+            let public_id = json["public_id"].as_str().unwrap_or_default();
+
             tracing::info!("Upload successful, URL: {}", secure_url);
-            Ok(secure_url.to_string())
+            Ok(UploadedImage {
+                url: secure_url.to_string(),
+                public_id: public_id.to_string(),
+            })
         } else {
             let error_text = response.text().await.unwrap_or_default();
             tracing::error!("Cloudinary upload failed with status {}: {}", status, error_text);
@@ -126,6 +165,36 @@ impl CloudinaryService {
         }
     }
 
+    pub async fn delete_image(&self, public_id: &str) -> Result<()> {
+        let timestamp_str = chrono::Utc::now().timestamp().to_string();
+
+        let mut params_for_signature = HashMap::new();
+        params_for_signature.insert("timestamp", timestamp_str.as_str());
+        params_for_signature.insert("public_id", public_id);
+        let signature = self.generate_signature(&params_for_signature)?;
+
+        let url = format!("https://api.cloudinary.com/v1_1/{}/image/destroy", self.config.cloud_name);
+
+        let response = self.client
+            .post(&url)
+            .form(&[
+                ("public_id", public_id),
+                ("timestamp", timestamp_str.as_str()),
+                ("api_key", self.config.api_key.as_str()),
+                ("signature", signature.as_str()),
+            ])
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            let error_text = response.text().await.unwrap_or_default();
+            Err(anyhow::anyhow!("Cloudinary delete failed with status {}: {}", status, error_text))
+        }
+    }
+
     fn generate_signature(&self, params: &HashMap<&str, &str>) -> Result<String> {
         use std::collections::BTreeMap;
         
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+const DEFAULT_POST_TTL_DAYS: i64 = 90;
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug, Clone)]
+pub struct ExpiryConfig {
+    pub post_ttl: Option<Duration>, // None when POST_TTL_DAYS=0, i.e. posts never expire by default
+    pub sweep_interval: std::time::Duration,
+}
+
+impl ExpiryConfig {
+    pub fn from_env() -> Result<Self> {
+        let ttl_days = match std::env::var("POST_TTL_DAYS") {
+            Ok(days) => days
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("POST_TTL_DAYS must be a whole number of days"))?,
+            Err(_) => DEFAULT_POST_TTL_DAYS,
+        };
+
+        let sweep_secs = match std::env::var("EXPIRY_SWEEP_INTERVAL_SECS") {
+            Ok(secs) => secs.parse::<u64>().map_err(|_| {
+                anyhow::anyhow!("EXPIRY_SWEEP_INTERVAL_SECS must be a whole number of seconds")
+            })?,
+            Err(_) => DEFAULT_SWEEP_INTERVAL_SECS,
+        };
+
+        Ok(Self {
+            post_ttl: (ttl_days > 0).then(|| Duration::days(ttl_days)),
+            sweep_interval: std::time::Duration::from_secs(sweep_secs.max(1)),
+        })
+    }
+
+    pub fn default_expiry(&self) -> Option<DateTime<Utc>> {
+        self.post_ttl.map(|ttl| Utc::now() + ttl)
+    }
+}
+
+pub fn spawn_expiry_sweeper(pool: PgPool, config: ExpiryConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.sweep_interval);
+
+        loop {
+            interval.tick().await;
+
+            match archive_expired_posts(&pool).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Archived {} expired posts", count),
+                Err(e) => tracing::error!("Failed to archive expired posts: {:?}", e),
+            }
+        }
+    });
+}
+
+async fn archive_expired_posts(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query!(
+        "UPDATE posts SET status = 'expired', version = version + 1
+         WHERE status IN ('open', 'in_progress') AND expires_at <= NOW() AND deleted_at IS NULL"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,87 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::structs::FollowResponse;
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use http::StatusCode;
+use sqlx::PgPool;
+use tower_sessions::Session;
+
+pub async fn follow_user(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(user_id): Path<i32>,
+) -> Result<Json<FollowResponse>, AppError> {
+    let follower_id = get_my_user_id(session).await?.0;
+
+    if follower_id == user_id {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("You cannot follow yourself"),
+        ));
+    }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1) as "exists!""#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("User with id {} not found.", user_id),
+        ));
+    }
+
+    // Following twice is a no-op
+    sqlx::query!(
+        "INSERT INTO follows (follower_id, followee_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        follower_id,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(FollowResponse {
+        user_id,
+        following: true,
+    }))
+}
+
+pub async fn unfollow_user(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(user_id): Path<i32>,
+) -> Result<Json<FollowResponse>, AppError> {
+    let follower_id = get_my_user_id(session).await?.0;
+
+    sqlx::query!(
+        "DELETE FROM follows WHERE follower_id = $1 AND followee_id = $2",
+        follower_id,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Json(FollowResponse {
+        user_id,
+        following: false,
+    }))
+}
+
+// How many users follow this user, and how many they follow, for their profile
+pub async fn follow_counts(pool: &PgPool, user_id: i32) -> Result<(i64, i64), AppError> {
+    let counts = sqlx::query!(
+        r#"SELECT (SELECT COUNT(*) FROM follows WHERE followee_id = $1) as "followers!",
+                (SELECT COUNT(*) FROM follows WHERE follower_id = $1) as "following!""#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((counts.followers, counts.following))
+}
@@ -0,0 +1,279 @@
+use crate::error::AppError;
+use crate::structs::PinLocation;
+use anyhow::Context;
+use http::StatusCode;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+
+pub const DEFAULT_RADIUS_KM: f64 = 25.0;
+pub const MAX_RADIUS_KM: f64 = 500.0;
+
+// Rough bounding box of India; the public directory has swapped or zeroed coordinates on some rows
+const LATITUDE_RANGE: std::ops::RangeInclusive<f64> = 6.0..=37.5;
+const LONGITUDE_RANGE: std::ops::RangeInclusive<f64> = 68.0..=97.5;
+
+#[derive(Debug, Clone)]
+pub struct PinCodeConfig {
+    pub require_known: bool, // Reject pins missing from the gazetteer, once it has been imported
+}
+
+impl PinCodeConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let require_known = match std::env::var("PIN_CODE_REQUIRE_KNOWN") {
+            Ok(value) => value
+                .parse::<bool>()
+                .map_err(|_| anyhow::anyhow!("PIN_CODE_REQUIRE_KNOWN must be true or false"))?,
+            Err(_) => false,
+        };
+
+        Ok(Self { require_known })
+    }
+}
+
+#[derive(Default)]
+struct PinEntry {
+    locality: String,
+    district: String,
+    state: String,
+    latitudes: Vec<f64>,
+    longitudes: Vec<f64>,
+}
+
+// Loads a CSV in the layout of the data.gov.in "All India Pincode Directory"
+// (officename, pincode, district, statename, latitude, longitude; extra columns are ignored).
+// The directory lists every post office, so rows are folded into one entry per pin.
+pub async fn import_pin_codes(pool: &PgPool, path: &str) -> anyhow::Result<usize> {
+    let mut reader =
+        csv::Reader::from_path(path).with_context(|| format!("Cannot open {}", path))?;
+
+    let headers = reader.headers()?.clone();
+    let column = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|h| names.contains(&h.trim().to_ascii_lowercase().as_str()))
+            .with_context(|| format!("Missing column {}", names[0]))
+    };
+    let pin_col = column(&["pincode", "pin_code"])?;
+    let office_col = column(&["officename", "locality"])?;
+    let district_col = column(&["district", "districtname"])?;
+    let state_col = column(&["statename", "state"])?;
+    let lat_col = column(&["latitude"])?;
+    let lon_col = column(&["longitude"])?;
+
+    let mut entries: BTreeMap<String, PinEntry> = BTreeMap::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: usize| record.get(i).unwrap_or_default().trim();
+
+        let pin = field(pin_col);
+        if pin.len() != 6 || !pin.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+
+        let entry = entries.entry(pin.to_string()).or_default();
+        if entry.locality.is_empty() {
+            entry.locality = office_name(field(office_col));
+            entry.district = field(district_col).to_string();
+            entry.state = field(state_col).to_string();
+        }
+
+        if let (Ok(lat), Ok(lon)) = (field(lat_col).parse::<f64>(), field(lon_col).parse::<f64>())
+            && LATITUDE_RANGE.contains(&lat)
+            && LONGITUDE_RANGE.contains(&lon)
+        {
+            entry.latitudes.push(lat);
+            entry.longitudes.push(lon);
+        }
+    }
+
+    let mut pins = Vec::with_capacity(entries.len());
+    let mut localities = Vec::with_capacity(entries.len());
+    let mut districts = Vec::with_capacity(entries.len());
+    let mut states = Vec::with_capacity(entries.len());
+    let mut latitudes = Vec::with_capacity(entries.len());
+    let mut longitudes = Vec::with_capacity(entries.len());
+
+    for (pin, entry) in entries {
+        pins.push(pin);
+        localities.push(entry.locality);
+        districts.push(entry.district);
+        states.push(entry.state);
+        latitudes.push(mean(&entry.latitudes));
+        longitudes.push(mean(&entry.longitudes));
+    }
+
+    sqlx::query!(
+        "INSERT INTO pin_codes (pin_code, locality, district, state, latitude, longitude)
+         SELECT * FROM unnest($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::FLOAT8[], $6::FLOAT8[])
+         ON CONFLICT (pin_code) DO UPDATE SET
+             locality = EXCLUDED.locality,
+             district = EXCLUDED.district,
+             state = EXCLUDED.state,
+             latitude = EXCLUDED.latitude,
+             longitude = EXCLUDED.longitude",
+        &pins,
+        &localities,
+        &districts,
+        &states,
+        &latitudes as &[Option<f64>],
+        &longitudes as &[Option<f64>]
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(pins.len())
+}
+
+// The full directory has around 19,000 pins; far fewer means only the sample (or nothing) is loaded
+const FULL_GAZETTEER_PINS: i64 = 15_000;
+
+// Called at startup: `near`, geojson and post locations only work for pins in the gazetteer
+pub async fn warn_if_incomplete(pool: &PgPool) -> anyhow::Result<()> {
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM pin_codes"#)
+        .fetch_one(pool)
+        .await?;
+
+    if count < FULL_GAZETTEER_PINS {
+        tracing::warn!(
+            "The pin-code gazetteer only has {} entries; load the full directory with scripts/import_pin_codes.sh",
+            count
+        );
+    }
+
+    Ok(())
+}
+
+// "Kamand S.O" -> "Kamand"; the office type suffix means nothing to users
+fn office_name(raw: &str) -> String {
+    let name = raw.trim();
+    [" B.O", " S.O", " H.O", " G.P.O.", " G.P.O"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name)
+        .trim()
+        .to_string()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+// `near` is either a pin code from the gazetteer or "lat,lon"
+pub async fn resolve_origin(pool: &PgPool, near: &str) -> Result<(f64, f64), AppError> {
+    let near = near.trim();
+
+    if let Some((lat, lon)) = near.split_once(',') {
+        let coordinates = lat
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .zip(lon.trim().parse::<f64>().ok());
+        return match coordinates {
+            Some((lat, lon))
+                if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) =>
+            {
+                Ok((lat, lon))
+            }
+            _ => Err(AppError::HttpError(
+                StatusCode::BAD_REQUEST,
+                anyhow::anyhow!("near must be a pin code or \"lat,lon\""),
+            )),
+        };
+    }
+
+    let location = sqlx::query!(
+        r#"SELECT latitude as "latitude!", longitude as "longitude!" FROM pin_codes
+         WHERE pin_code = $1 AND latitude IS NOT NULL AND longitude IS NOT NULL"#,
+        near
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    location.map(|l| (l.latitude, l.longitude)).ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("No location known for pin code {}", near),
+        )
+    })
+}
+
+pub fn validate_radius(radius_km: Option<f64>) -> Result<f64, AppError> {
+    match radius_km {
+        None => Ok(DEFAULT_RADIUS_KM),
+        Some(r) if r > 0.0 && r <= MAX_RADIUS_KM => Ok(r),
+        Some(_) => Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("radius_km must be between 0 and {}", MAX_RADIUS_KM),
+        )),
+    }
+}
+
+// Drops all whitespace, so "110 001" and " 110001 " both become "110001"; blank means no pin
+pub fn normalize_pin_code(raw: &str) -> Option<String> {
+    let pin: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+    (!pin.is_empty()).then_some(pin)
+}
+
+// Describes what is wrong with a normalised pin code, for use in a field-level error
+pub async fn pin_code_problem(pool: &PgPool, pin: &str) -> Result<Option<String>, AppError> {
+    if pin.len() != 6 || !pin.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(Some("must be exactly six digits".to_string()));
+    }
+
+    // The first digit is the postal zone: 1-8 are regions, 9 is the Army Postal Service
+    if pin.starts_with('0') {
+        return Ok(Some(
+            "must start with a postal zone digit from 1 to 9".to_string(),
+        ));
+    }
+
+    let config = PinCodeConfig::from_env()
+        .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    if config.require_known {
+        let known = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM pin_codes WHERE pin_code = $1) as "exists!""#,
+            pin
+        )
+        .fetch_one(pool)
+        .await?;
+
+        if !known {
+            return Ok(Some(format!("{} is not a known pin code", pin)));
+        }
+    }
+
+    Ok(None)
+}
+
+// Normalises an optional pin code and rejects a bad one with a field-level 400
+pub async fn validate_pin_code(
+    pool: &PgPool,
+    raw: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    let Some(pin) = raw.and_then(normalize_pin_code) else {
+        return Ok(None);
+    };
+
+    match pin_code_problem(pool, &pin).await? {
+        Some(problem) => Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Invalid fields: pin_code: {}", problem),
+        )),
+        None => Ok(Some(pin)),
+    }
+}
+
+// Rows LEFT JOIN pin_codes, so the three columns are either all set or all NULL
+pub fn pin_location(
+    locality: Option<String>,
+    district: Option<String>,
+    state: Option<String>,
+) -> Option<PinLocation> {
+    Some(PinLocation {
+        locality: locality?,
+        district: district?,
+        state: state?,
+    })
+}
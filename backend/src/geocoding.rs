@@ -0,0 +1,596 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::gazetteer::{normalize_pin_code, pin_code_problem};
+use crate::structs::{GeoPlace, ReverseQuery};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::Duration;
+use http::StatusCode;
+use serde::Deserialize;
+use sqlx::{PgPool, types::Json as JsonColumn};
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use tokio::time::Instant;
+use tower_sessions::Session;
+
+const DEFAULT_BASE_URL: &str = "https://nominatim.openstreetmap.org";
+const DEFAULT_USER_AGENT: &str = "skillswap-backend";
+const DEFAULT_CACHE_TTL_DAYS: i64 = 30;
+const DEFAULT_MIN_INTERVAL_MS: u64 = 1000; // Nominatim's public policy: at most 1 request per second
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const MAX_THROTTLE_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Shared by every request, so connections are reused and the rate limit is server-wide
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build geocoder HTTP client")
+});
+static PROVIDER_THROTTLE: Throttle = Throttle::new();
+
+#[derive(Debug, Clone)]
+pub struct GeocoderConfig {
+    pub base_url: String, // Any Nominatim-compatible server, e.g. a local stand-in for tests
+    pub user_agent: String, // Nominatim's usage policy requires an identifying agent
+    pub cache_ttl: Duration,
+    pub min_interval: std::time::Duration, // Between two requests to the provider
+}
+
+impl GeocoderConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let ttl_days = match std::env::var("GEOCODE_CACHE_TTL_DAYS") {
+            Ok(days) => days.parse::<i64>().map_err(|_| {
+                anyhow::anyhow!("GEOCODE_CACHE_TTL_DAYS must be a whole number of days")
+            })?,
+            Err(_) => DEFAULT_CACHE_TTL_DAYS,
+        };
+
+        let interval_ms = match std::env::var("GEOCODER_MIN_INTERVAL_MS") {
+            Ok(ms) => ms.parse::<u64>().map_err(|_| {
+                anyhow::anyhow!("GEOCODER_MIN_INTERVAL_MS must be a whole number of milliseconds")
+            })?,
+            Err(_) => DEFAULT_MIN_INTERVAL_MS,
+        };
+
+        Ok(Self {
+            base_url: std::env::var("NOMINATIM_BASE_URL")
+                .unwrap_or_else(|_| DEFAULT_BASE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            user_agent: std::env::var("GEOCODER_USER_AGENT")
+                .unwrap_or_else(|_| DEFAULT_USER_AGENT.to_string()),
+            cache_ttl: Duration::days(ttl_days.max(0)),
+            min_interval: std::time::Duration::from_millis(interval_ms),
+        })
+    }
+}
+
+pub trait Geocoder {
+    fn pin_code(&self, pin: &str) -> impl Future<Output = anyhow::Result<Option<GeoPlace>>> + Send;
+
+    fn reverse(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> impl Future<Output = anyhow::Result<Option<GeoPlace>>> + Send;
+}
+
+// Returned when the provider's request queue is already longer than MAX_THROTTLE_WAIT
+#[derive(Debug)]
+pub struct GeocoderBusy;
+
+impl std::fmt::Display for GeocoderBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Geocoder rate limit reached")
+    }
+}
+
+impl std::error::Error for GeocoderBusy {}
+
+// Hands out request slots at least `interval` apart
+pub struct Throttle {
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub const fn new() -> Self {
+        Self {
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    // Books the next free slot, or None when it is further than `max_wait` away
+    fn reserve(
+        &self,
+        now: Instant,
+        interval: std::time::Duration,
+        max_wait: std::time::Duration,
+    ) -> Option<Instant> {
+        let mut next_slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = next_slot.map_or(now, |next| next.max(now));
+
+        if slot - now > max_wait {
+            return None;
+        }
+
+        *next_slot = Some(slot + interval);
+        Some(slot)
+    }
+
+    async fn wait(&self, interval: std::time::Duration) -> Result<(), GeocoderBusy> {
+        let slot = self
+            .reserve(Instant::now(), interval, MAX_THROTTLE_WAIT)
+            .ok_or(GeocoderBusy)?;
+        tokio::time::sleep_until(slot).await;
+        Ok(())
+    }
+}
+
+pub struct NominatimGeocoder {
+    base_url: String,
+    user_agent: String,
+    min_interval: std::time::Duration,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    lat: String,
+    lon: String,
+    display_name: String,
+    #[serde(default)]
+    address: NominatimAddress,
+}
+
+#[derive(Deserialize, Default)]
+struct NominatimAddress {
+    postcode: Option<String>,
+}
+
+impl NominatimPlace {
+    fn into_place(self) -> anyhow::Result<GeoPlace> {
+        Ok(GeoPlace {
+            latitude: self.lat.parse()?,
+            longitude: self.lon.parse()?,
+            display_name: self.display_name,
+            postcode: self.address.postcode,
+        })
+    }
+}
+
+impl NominatimGeocoder {
+    pub fn new(config: &GeocoderConfig) -> Self {
+        Self {
+            base_url: config.base_url.clone(),
+            user_agent: config.user_agent.clone(),
+            min_interval: config.min_interval,
+            client: HTTP_CLIENT.clone(),
+        }
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, String)]) -> anyhow::Result<serde_json::Value> {
+        PROVIDER_THROTTLE.wait(self.min_interval).await?;
+
+        let response = self
+            .client
+            .get(format!("{}/{}", self.base_url, path))
+            .header(reqwest::header::USER_AGENT, &self.user_agent)
+            .query(query)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("Geocoder responded with status {}", status));
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+impl Geocoder for NominatimGeocoder {
+    async fn pin_code(&self, pin: &str) -> anyhow::Result<Option<GeoPlace>> {
+        let results = self
+            .get(
+                "search",
+                &[
+                    ("format", "json".to_string()),
+                    ("countrycodes", "in".to_string()),
+                    ("postalcode", pin.to_string()),
+                    ("addressdetails", "1".to_string()),
+                    ("limit", "1".to_string()),
+                ],
+            )
+            .await?;
+
+        let places: Vec<NominatimPlace> = serde_json::from_value(results)?;
+        places
+            .into_iter()
+            .next()
+            .map(NominatimPlace::into_place)
+            .transpose()
+    }
+
+    async fn reverse(&self, latitude: f64, longitude: f64) -> anyhow::Result<Option<GeoPlace>> {
+        let result = self
+            .get(
+                "reverse",
+                &[
+                    ("format", "json".to_string()),
+                    ("lat", latitude.to_string()),
+                    ("lon", longitude.to_string()),
+                    ("zoom", "18".to_string()),
+                    ("addressdetails", "1".to_string()),
+                ],
+            )
+            .await?;
+
+        // Nominatim answers {"error": "Unable to geocode"} when there is nothing at the point
+        if result.get("error").is_some() {
+            return Ok(None);
+        }
+
+        serde_json::from_value::<NominatimPlace>(result)?
+            .into_place()
+            .map(Some)
+    }
+}
+
+// Wraps any geocoder with the geocode_cache table; misses are cached as well as hits
+pub struct CachedGeocoder<G> {
+    inner: G,
+    pool: PgPool,
+    ttl: Duration,
+}
+
+impl<G: Geocoder + Sync> CachedGeocoder<G> {
+    pub fn new(inner: G, pool: PgPool, ttl: Duration) -> Self {
+        Self { inner, pool, ttl }
+    }
+
+    async fn cached(
+        &self,
+        key: String,
+        fetch: impl Future<Output = anyhow::Result<Option<GeoPlace>>> + Send,
+    ) -> anyhow::Result<Option<GeoPlace>> {
+        let cutoff = chrono::Utc::now() - self.ttl;
+
+        let hit = sqlx::query!(
+            r#"SELECT result as "result: JsonColumn<GeoPlace>" FROM geocode_cache
+             WHERE cache_key = $1 AND fetched_at > $2"#,
+            key,
+            cutoff
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(hit) = hit {
+            return Ok(hit.result.map(|place| place.0));
+        }
+
+        let place = fetch.await?;
+
+        sqlx::query!(
+            "INSERT INTO geocode_cache (cache_key, result, fetched_at) VALUES ($1, $2, NOW())
+             ON CONFLICT (cache_key) DO UPDATE SET result = EXCLUDED.result, fetched_at = EXCLUDED.fetched_at",
+            key,
+            place.clone().map(JsonColumn) as Option<JsonColumn<GeoPlace>>
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(place)
+    }
+}
+
+impl<G: Geocoder + Sync> Geocoder for CachedGeocoder<G> {
+    async fn pin_code(&self, pin: &str) -> anyhow::Result<Option<GeoPlace>> {
+        self.cached(format!("pin:{}", pin), self.inner.pin_code(pin))
+            .await
+    }
+
+    // Points are rounded to about 10 m so nearby clicks share a cache entry
+    async fn reverse(&self, latitude: f64, longitude: f64) -> anyhow::Result<Option<GeoPlace>> {
+        let (latitude, longitude) = (round4(latitude), round4(longitude));
+        self.cached(
+            format!("reverse:{:.4},{:.4}", latitude, longitude),
+            self.inner.reverse(latitude, longitude),
+        )
+        .await
+    }
+}
+
+fn round4(value: f64) -> f64 {
+    (value * 10_000.0).round() / 10_000.0
+}
+
+fn geocoder(pool: &PgPool) -> Result<CachedGeocoder<NominatimGeocoder>, AppError> {
+    let config = GeocoderConfig::from_env()
+        .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(CachedGeocoder::new(
+        NominatimGeocoder::new(&config),
+        pool.clone(),
+        config.cache_ttl,
+    ))
+}
+
+fn provider_error(e: anyhow::Error) -> AppError {
+    if e.is::<GeocoderBusy>() {
+        return AppError::HttpError(
+            StatusCode::TOO_MANY_REQUESTS,
+            anyhow::anyhow!("Geocoding service is busy, try again shortly"),
+        );
+    }
+
+    tracing::error!("Geocoding failed: {:?}", e);
+    AppError::HttpError(
+        StatusCode::BAD_GATEWAY,
+        anyhow::anyhow!("Geocoding service unavailable"),
+    )
+}
+
+// Answers from the imported gazetteer when it can, and only asks the provider otherwise
+pub async fn geocode_pin_code(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(pin): Path<String>,
+) -> Result<Json<GeoPlace>, AppError> {
+    let _user_id = get_my_user_id(session).await?.0;
+
+    let pin = normalize_pin_code(&pin).unwrap_or_default();
+
+    if let Some(problem) = pin_code_problem(&pool, &pin).await? {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Invalid fields: pin_code: {}", problem),
+        ));
+    }
+
+    let known = sqlx::query!(
+        r#"SELECT locality, district, state, latitude as "latitude!", longitude as "longitude!"
+         FROM pin_codes WHERE pin_code = $1 AND latitude IS NOT NULL AND longitude IS NOT NULL"#,
+        pin
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let place = match known {
+        Some(row) => Some(GeoPlace {
+            latitude: row.latitude,
+            longitude: row.longitude,
+            display_name: format!("{}, {}, {}, {}", row.locality, row.district, row.state, pin),
+            postcode: Some(pin.clone()),
+        }),
+        None => geocoder(&pool)?
+            .pin_code(&pin)
+            .await
+            .map_err(provider_error)?,
+    };
+
+    place.map(Json).ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("No location found for pin code {}", pin),
+        )
+    })
+}
+
+pub async fn reverse_geocode(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(query): Query<ReverseQuery>,
+) -> Result<Json<GeoPlace>, AppError> {
+    let _user_id = get_my_user_id(session).await?.0;
+
+    if !(-90.0..=90.0).contains(&query.lat) || !(-180.0..=180.0).contains(&query.lon) {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("lat must be within ±90 and lon within ±180"),
+        ));
+    }
+
+    let place = geocoder(&pool)?
+        .reverse(query.lat, query.lon)
+        .await
+        .map_err(provider_error)?;
+
+    place.map(Json).ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("No address found at {}, {}", query.lat, query.lon),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Answers every lookup with the same place (or nothing) and counts how often it is asked
+    struct StubGeocoder {
+        place: Option<GeoPlace>,
+        calls: AtomicUsize,
+    }
+
+    impl StubGeocoder {
+        fn new(place: Option<GeoPlace>) -> Self {
+            Self {
+                place,
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Geocoder for &StubGeocoder {
+        async fn pin_code(&self, _pin: &str) -> anyhow::Result<Option<GeoPlace>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.place.clone())
+        }
+
+        async fn reverse(&self, _lat: f64, _lon: f64) -> anyhow::Result<Option<GeoPlace>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.place.clone())
+        }
+    }
+
+    fn mandi() -> GeoPlace {
+        GeoPlace {
+            latitude: 31.7754,
+            longitude: 76.9861,
+            display_name: "Kamand, Mandi, Himachal Pradesh".to_string(),
+            postcode: Some("175075".to_string()),
+        }
+    }
+
+    #[sqlx::test]
+    async fn hit_is_served_from_the_cache(pool: PgPool) {
+        let stub = StubGeocoder::new(Some(mandi()));
+        let geocoder = CachedGeocoder::new(&stub, pool, Duration::days(30));
+
+        let first = geocoder.pin_code("175075").await.unwrap();
+        let second = geocoder.pin_code("175075").await.unwrap();
+
+        assert_eq!(stub.calls(), 1);
+        assert_eq!(first.unwrap().display_name, mandi().display_name);
+        assert_eq!(second.unwrap().display_name, mandi().display_name);
+    }
+
+    #[sqlx::test]
+    async fn miss_is_cached_too(pool: PgPool) {
+        let stub = StubGeocoder::new(None);
+        let geocoder = CachedGeocoder::new(&stub, pool, Duration::days(30));
+
+        assert!(geocoder.pin_code("999999").await.unwrap().is_none());
+        assert!(geocoder.pin_code("999999").await.unwrap().is_none());
+        assert_eq!(stub.calls(), 1);
+    }
+
+    #[sqlx::test]
+    async fn expired_entry_is_fetched_again(pool: PgPool) {
+        let stub = StubGeocoder::new(Some(mandi()));
+        let geocoder = CachedGeocoder::new(&stub, pool.clone(), Duration::days(30));
+
+        geocoder.pin_code("175075").await.unwrap();
+        sqlx::query("UPDATE geocode_cache SET fetched_at = NOW() - INTERVAL '31 days'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        geocoder.pin_code("175075").await.unwrap();
+        geocoder.pin_code("175075").await.unwrap();
+
+        assert_eq!(stub.calls(), 2);
+    }
+
+    #[sqlx::test]
+    async fn nearby_points_share_an_entry(pool: PgPool) {
+        let stub = StubGeocoder::new(Some(mandi()));
+        let geocoder = CachedGeocoder::new(&stub, pool, Duration::days(30));
+
+        geocoder.reverse(31.77541, 76.98612).await.unwrap();
+        geocoder.reverse(31.77543, 76.98609).await.unwrap();
+        geocoder.reverse(31.7761, 76.9861).await.unwrap();
+
+        assert_eq!(stub.calls(), 2);
+    }
+
+    // A Nominatim stand-in that knows one pin code and one point, and nothing else
+    async fn start_nominatim_stub() -> String {
+        use axum::{Router, routing::get};
+        use std::collections::HashMap;
+
+        async fn search(
+            headers: http::HeaderMap,
+            Query(query): Query<HashMap<String, String>>,
+        ) -> Json<serde_json::Value> {
+            assert_eq!(headers[http::header::USER_AGENT], DEFAULT_USER_AGENT);
+            assert_eq!(query["format"], "json");
+            assert_eq!(query["countrycodes"], "in");
+
+            if query["postalcode"] == "175075" {
+                Json(serde_json::json!([{
+                    "lat": "31.7754",
+                    "lon": "76.9861",
+                    "display_name": "Kamand, Mandi, Himachal Pradesh",
+                    "address": { "postcode": "175075" }
+                }]))
+            } else {
+                Json(serde_json::json!([]))
+            }
+        }
+
+        async fn reverse(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            if query["lat"].starts_with("31.77") {
+                Json(serde_json::json!({
+                    "lat": "31.7754",
+                    "lon": "76.9861",
+                    "display_name": "Kamand, Mandi, Himachal Pradesh",
+                    "address": { "postcode": "175075" }
+                }))
+            } else {
+                Json(serde_json::json!({ "error": "Unable to geocode" }))
+            }
+        }
+
+        let app = Router::new()
+            .route("/search", get(search))
+            .route("/reverse", get(reverse));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{}/", address)
+    }
+
+    #[tokio::test]
+    async fn nominatim_parses_hits_and_empty_answers() {
+        let base_url = start_nominatim_stub().await;
+        // No other test reads these variables
+        unsafe {
+            std::env::set_var("NOMINATIM_BASE_URL", &base_url);
+            std::env::set_var("GEOCODER_MIN_INTERVAL_MS", "0");
+        }
+        let geocoder = NominatimGeocoder::new(&GeocoderConfig::from_env().unwrap());
+
+        let place = geocoder.pin_code("175075").await.unwrap().unwrap();
+        assert_eq!(place.display_name, mandi().display_name);
+        assert_eq!(place.latitude, 31.7754);
+        assert_eq!(place.postcode.as_deref(), Some("175075"));
+        assert!(geocoder.pin_code("999999").await.unwrap().is_none());
+
+        let place = geocoder.reverse(31.7754, 76.9861).await.unwrap().unwrap();
+        assert_eq!(place.longitude, 76.9861);
+        assert!(geocoder.reverse(0.0, 0.0).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn throttle_spaces_out_slots_and_turns_away_long_waits() {
+        let throttle = Throttle::new();
+        let interval = std::time::Duration::from_secs(1);
+        let max_wait = std::time::Duration::from_secs(2);
+        let now = Instant::now();
+
+        assert_eq!(throttle.reserve(now, interval, max_wait), Some(now));
+        assert_eq!(
+            throttle.reserve(now, interval, max_wait),
+            Some(now + interval)
+        );
+        assert_eq!(
+            throttle.reserve(now, interval, max_wait),
+            Some(now + interval * 2)
+        );
+        assert_eq!(throttle.reserve(now, interval, max_wait), None);
+
+        // Once the queue has drained, the next caller goes straight through
+        let later = now + interval * 10;
+        assert_eq!(throttle.reserve(later, interval, max_wait), Some(later));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::markdown::render_description;
+use crate::structs::GeoJsonQuery;
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use http::{HeaderName, StatusCode, header};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use tower_sessions::Session;
+
+// Below this zoom posts are merged into grid clusters; from it on every post is its own point
+const CLUSTER_BELOW_ZOOM: u8 = 12;
+// Roughly 64px cells on 256px map tiles
+const CELLS_PER_TILE: f64 = 4.0;
+const MAX_POINT_FEATURES: i64 = 1000;
+const TOP_CATEGORIES: i64 = 3;
+
+type GeoJsonResponse = ([(HeaderName, &'static str); 1], Json<Value>);
+
+fn parse_bbox(bbox: Option<&str>) -> Result<[f64; 4], AppError> {
+    let Some(bbox) = bbox.filter(|b| !b.trim().is_empty()) else {
+        return Ok([-180.0, -90.0, 180.0, 90.0]);
+    };
+
+    let invalid = || {
+        AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("bbox must be \"min_lon,min_lat,max_lon,max_lat\""),
+        )
+    };
+
+    let values: Vec<f64> = bbox
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+
+    match values[..] {
+        [min_lon, min_lat, max_lon, max_lat] if min_lon <= max_lon && min_lat <= max_lat => {
+            Ok([min_lon, min_lat, max_lon, max_lat])
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// Open community posts as a GeoJSON FeatureCollection, placed at their pin code's coordinates.
+// At low zoom the response holds one cluster feature per grid cell instead of individual posts.
+pub async fn community_geojson(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(query): Query<GeoJsonQuery>,
+) -> Result<GeoJsonResponse, AppError> {
+    let _user_id = get_my_user_id(session).await?.0;
+
+    let [min_lon, min_lat, max_lon, max_lat] = parse_bbox(query.bbox.as_deref())?;
+    let zoom = query.zoom.unwrap_or(0).min(22);
+    let post_type = query.post_type.map(|t| t.to_string());
+
+    let features = if zoom < CLUSTER_BELOW_ZOOM {
+        let cell_size = 360.0 / 2f64.powi(zoom as i32) / CELLS_PER_TILE;
+        cluster_features(
+            &pool,
+            [min_lon, min_lat, max_lon, max_lat],
+            cell_size,
+            post_type,
+        )
+        .await?
+    } else {
+        point_features(&pool, [min_lon, min_lat, max_lon, max_lat], post_type).await?
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        Json(json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })),
+    ))
+}
+
+async fn cluster_features(
+    pool: &PgPool,
+    [min_lon, min_lat, max_lon, max_lat]: [f64; 4],
+    cell_size: f64,
+    post_type: Option<String>,
+) -> Result<Vec<Value>, AppError> {
+    let clusters = sqlx::query!(
+        r#"WITH located AS (
+             SELECT p.post_type, p.categories, loc.latitude, loc.longitude,
+                    floor(loc.longitude / $5::FLOAT8) AS cell_x, floor(loc.latitude / $5) AS cell_y
+             FROM posts p
+             JOIN pin_codes loc ON loc.pin_code = p.pin_code
+             WHERE p.deleted_at IS NULL
+               AND p.status = 'open'
+               AND ($6::TEXT IS NULL OR p.post_type = $6)
+               AND loc.longitude BETWEEN $1 AND $3
+               AND loc.latitude BETWEEN $2 AND $4
+         )
+         SELECT count(*) as "count!",
+                count(*) FILTER (WHERE c.post_type = 'offer') as "offers!",
+                count(*) FILTER (WHERE c.post_type = 'request') as "requests!",
+                avg(c.longitude) as "longitude!",
+                avg(c.latitude) as "latitude!",
+                ARRAY(SELECT category FROM located l, unnest(l.categories) category
+                      WHERE l.cell_x = c.cell_x AND l.cell_y = c.cell_y
+                      GROUP BY category
+                      ORDER BY count(*) DESC, category
+                      LIMIT $7) as "top_categories!"
+         FROM located c
+         GROUP BY c.cell_x, c.cell_y
+         ORDER BY count(*) DESC"#,
+        min_lon,
+        min_lat,
+        max_lon,
+        max_lat,
+        cell_size,
+        post_type,
+        TOP_CATEGORIES
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(clusters
+        .into_iter()
+        .map(|cluster| {
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [cluster.longitude, cluster.latitude],
+                },
+                "properties": {
+                    "cluster": true,
+                    "count": cluster.count,
+                    "offers": cluster.offers,
+                    "requests": cluster.requests,
+                    "top_categories": cluster.top_categories,
+                },
+            })
+        })
+        .collect())
+}
+
+async fn point_features(
+    pool: &PgPool,
+    [min_lon, min_lat, max_lon, max_lat]: [f64; 4],
+    post_type: Option<String>,
+) -> Result<Vec<Value>, AppError> {
+    let posts = sqlx::query!(
+        r#"SELECT p.id, p.description, p.categories, p.post_type, p.pin_code as "pin_code!", p.user_id, u.name as "user_name?", u.profile_picture as "profile_picture?",
+                  p.created_at, loc.locality, loc.district, loc.state,
+                  loc.latitude as "latitude!", loc.longitude as "longitude!"
+           FROM posts p
+           JOIN pin_codes loc ON loc.pin_code = p.pin_code
+           LEFT JOIN users u ON p.user_id = u.id
+           WHERE p.deleted_at IS NULL
+             AND p.status = 'open'
+             AND ($5::TEXT IS NULL OR p.post_type = $5)
+             AND loc.longitude BETWEEN $1 AND $3
+             AND loc.latitude BETWEEN $2 AND $4
+           ORDER BY p.created_at DESC, p.id DESC
+           LIMIT $6"#,
+        min_lon,
+        min_lat,
+        max_lon,
+        max_lat,
+        post_type,
+        MAX_POINT_FEATURES
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(posts
+        .into_iter()
+        .map(|post| {
+            json!({
+                "type": "Feature",
+                "id": post.id,
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [post.longitude, post.latitude],
+                },
+                "properties": {
+                    "cluster": false,
+                    "id": post.id,
+                    "description_html": render_description(&post.description),
+                    "description": post.description,
+                    "categories": post.categories,
+                    "post_type": post.post_type,
+                    "pin_code": post.pin_code,
+                    "location": {
+                        "locality": post.locality,
+                        "district": post.district,
+                        "state": post.state,
+                    },
+                    "user_id": post.user_id,
+                    "user_name": post.user_name,
+                    "profile_picture": post.profile_picture,
+                    "created_at": post.created_at,
+                },
+            })
+        })
+        .collect())
+}
@@ -1,30 +1,74 @@
 mod auth;
+mod bookings;
+mod bookmarks;
+mod categories;
 mod cloudinary;
 mod error;
+mod expiry;
+mod follows;
+mod gazetteer;
+mod geocoding;
+mod geojson;
+mod markdown;
+mod matches;
+mod messages;
+mod pagination;
 mod partitioned_cookies;
+mod post_images;
 mod posts;
+mod reviews;
+mod revisions;
+mod search;
 mod structs;
 mod telemetry;
+mod timebank;
+mod transfer;
+mod trash;
 use auth::{
     check_auth, get_my_profile, get_my_user_id, get_user_profile, login, logout, register,
     update_profile_picture,
 };
 use axum::{
-    Router, middleware,
+    Router,
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{delete, get, post},
 };
+use bookings::{
+    accept_booking, cancel_booking, complete_booking, create_booking, decline_booking, get_booking,
+    list_bookings, propose_booking_slots,
+};
+use bookmarks::{add_bookmark, list_bookmarks, remove_bookmark};
+use categories::list_categories;
 use error::AppError;
+use expiry::{ExpiryConfig, spawn_expiry_sweeper};
+use follows::{follow_user, unfollow_user};
+use geocoding::{geocode_pin_code, reverse_geocode};
+use geojson::community_geojson;
 use http::{HeaderName, Method};
+use matches::list_matches;
+use messages::{
+    get_conversation, get_unread_count, list_conversations, list_messages, mark_conversation_read,
+    send_message, start_conversation,
+};
 use partitioned_cookies::add_partitioned_attribute;
+use post_images::POST_BODY_LIMIT;
 use posts::{
-    create_post, delete_post, list_community_offers, list_community_posts, list_community_requests,
-    list_my_posts, list_offers, list_requests, update_post,
+    change_post_status, community_stats, create_post, delete_post, get_post, list_community_offers,
+    list_community_posts, list_community_requests, list_following_posts, list_my_posts,
+    list_offers, list_requests, list_trash, patch_post, renew_post, restore_post, update_post,
 };
+use reviews::create_review;
+use revisions::{diff_revisions, list_revisions};
+use search::search_community_posts;
 use sqlx::PgPool;
 use std::net::SocketAddr;
+use timebank::{get_my_credits, list_my_credit_history};
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 use tower_sessions::{MemoryStore, SessionManagerLayer};
+use transfer::{export_posts, import_posts};
+use trash::{TrashConfig, spawn_trash_purger};
 
 use crate::posts::list_user_posts;
 
@@ -41,17 +85,39 @@ async fn main() -> Result<(), AppError> {
     })?;
     tracing::info!("Successfully connected to database.");
 
+    // `backend import-pin-codes <file.csv>` loads the gazetteer and exits instead of serving
+    let mut args = std::env::args().skip(1);
+    if let Some(command) = args.next() {
+        return match (command.as_str(), args.next()) {
+            ("import-pin-codes", Some(path)) => {
+                let count = gazetteer::import_pin_codes(&pool, &path).await?;
+                tracing::info!("Imported {} pin codes from {}", count, path);
+                Ok(())
+            }
+            _ => Err(AppError::Internal(anyhow::anyhow!(
+                "Unknown command {:?}. Usage: backend [import-pin-codes <file.csv>]",
+                command
+            ))),
+        };
+    }
+
+    gazetteer::warn_if_incomplete(&pool).await?;
+    spawn_expiry_sweeper(pool.clone(), ExpiryConfig::from_env()?);
+    spawn_trash_purger(pool.clone(), TrashConfig::from_env()?);
+
     let cors = CorsLayer::new()
         .allow_origin([
             "http://localhost:3000".parse().unwrap(),
             std::env::var("FRONTEND_URL").unwrap().parse().unwrap(),
         ])
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_headers([
             HeaderName::from_static("content-type"),
             HeaderName::from_static("authorization"),
             HeaderName::from_static("accept"),
+            HeaderName::from_static("if-match"),
         ])
+        .expose_headers([HeaderName::from_static("etag")])
         .allow_credentials(true);
 
     let session_store = MemoryStore::default();
@@ -64,13 +130,36 @@ async fn main() -> Result<(), AppError> {
         .route("/posts", get(list_my_posts))
         .route("/posts/offers", get(list_offers))
         .route("/posts/requests", get(list_requests))
+        .route("/posts/trash", get(list_trash))
+        .route("/posts/export", get(export_posts))
+        .route("/posts/import", post(import_posts))
         .route("/foreignposts/{userid}", get(list_user_posts))
         .route("/community", get(list_community_posts))
         .route("/community/offers", get(list_community_offers))
         .route("/community/requests", get(list_community_requests))
-        .route("/posts/create", post(create_post))
+        .route("/community/search", get(search_community_posts))
+        .route("/community/following", get(list_following_posts))
+        .route("/community/stats", get(community_stats))
+        .route("/community.geojson", get(community_geojson))
+        .route("/categories", get(list_categories))
+        .route("/geo/pincode/{pin}", get(geocode_pin_code))
+        .route("/geo/reverse", get(reverse_geocode))
+        .route(
+            "/posts/create",
+            post(create_post).layer(DefaultBodyLimit::max(POST_BODY_LIMIT)),
+        )
         .route("/posts/delete/{id}", delete(delete_post))
-        .route("/posts/update", post(update_post))
+        .route(
+            "/posts/update",
+            post(update_post).layer(DefaultBodyLimit::max(POST_BODY_LIMIT)),
+        )
+        .route("/posts/{id}", get(get_post).patch(patch_post))
+        .route("/posts/{id}/status", post(change_post_status))
+        .route("/posts/{id}/renew", post(renew_post))
+        .route("/posts/{id}/matches", get(list_matches))
+        .route("/posts/{id}/restore", post(restore_post))
+        .route("/posts/{id}/revisions", get(list_revisions))
+        .route("/posts/{id}/revisions/diff", get(diff_revisions))
         .route("/auth/register", post(register))
         .route("/auth/login", post(login))
         .route("/auth/logout", post(logout))
@@ -78,7 +167,37 @@ async fn main() -> Result<(), AppError> {
         .route("/auth/myprofile", get(get_my_profile))
         .route("/auth/my_userid", get(get_my_user_id))
         .route("/auth/myprofile/picture", post(update_profile_picture))
+        .route("/auth/myprofile/credits", get(get_my_credits))
+        .route("/auth/myprofile/credits/history", get(list_my_credit_history))
         .route("/auth/userprofile/{user_id}", get(get_user_profile))
+        .route(
+            "/follows/{user_id}",
+            post(follow_user).delete(unfollow_user),
+        )
+        .route(
+            "/conversations",
+            get(list_conversations).post(start_conversation),
+        )
+        .route("/conversations/unread", get(get_unread_count))
+        .route("/conversations/{id}", get(get_conversation))
+        .route(
+            "/conversations/{id}/messages",
+            get(list_messages).post(send_message),
+        )
+        .route("/conversations/{id}/read", post(mark_conversation_read))
+        .route("/bookings", get(list_bookings).post(create_booking))
+        .route("/bookings/{id}", get(get_booking))
+        .route("/bookings/{id}/propose", post(propose_booking_slots))
+        .route("/bookings/{id}/accept", post(accept_booking))
+        .route("/bookings/{id}/decline", post(decline_booking))
+        .route("/bookings/{id}/cancel", post(cancel_booking))
+        .route("/bookings/{id}/complete", post(complete_booking))
+        .route("/bookings/{id}/review", post(create_review))
+        .route("/bookmarks", get(list_bookmarks))
+        .route(
+            "/bookmarks/{post_id}",
+            post(add_bookmark).delete(remove_bookmark),
+        )
         .with_state(pool)
         .layer(session_layer)
         .layer(middleware::from_fn(add_partitioned_attribute))
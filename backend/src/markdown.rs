@@ -0,0 +1,193 @@
+use pulldown_cmark::{CowStr, Event, HeadingLevel, LinkType, Options, Parser, Tag, TagEnd, html};
+
+// Post descriptions are shown on feed cards, so keep headings small and images few
+const MAX_IMAGES: usize = 1;
+const HEADING_DEMOTION: usize = 3;
+
+// Renders a Markdown description to HTML that is safe to inject into the page:
+// raw HTML is shown as text, only http(s)/mailto links survive and they never pass on referrer trust.
+pub fn render_description(markdown: &str) -> String {
+    let mut images = 0;
+    // Tracks whether each open link/image was kept, so the matching end tag can be dropped too
+    let mut open_links: Vec<bool> = Vec::new();
+    let mut open_images: Vec<bool> = Vec::new();
+
+    let events = Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH).filter_map(|event| {
+        match event {
+            Event::Html(raw) | Event::InlineHtml(raw) => Some(Event::Text(raw)),
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => None,
+
+            Event::Start(Tag::Heading { level, .. }) => Some(Event::Start(Tag::Heading {
+                level: demote(level),
+                id: None,
+                classes: Vec::new(),
+                attrs: Vec::new(),
+            })),
+            Event::End(TagEnd::Heading(level)) => Some(Event::End(TagEnd::Heading(demote(level)))),
+
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) => {
+                let href = match link_type {
+                    LinkType::Email => Some(format!("mailto:{}", dest_url)),
+                    _ => is_safe_url(&dest_url, true).then(|| dest_url.to_string()),
+                };
+                open_links.push(href.is_some());
+
+                href.map(|href| {
+                    Event::Html(CowStr::from(format!(
+                        "<a href=\"{}\" rel=\"nofollow noopener\">",
+                        escape_attr(&href)
+                    )))
+                })
+            }
+            Event::End(TagEnd::Link) => open_links
+                .pop()
+                .unwrap_or(false)
+                .then_some(Event::Html(CowStr::Borrowed("</a>"))),
+
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let keep = images < MAX_IMAGES && is_safe_url(&dest_url, false);
+                open_images.push(keep);
+
+                if keep {
+                    images += 1;
+                    Some(Event::Start(Tag::Image {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    }))
+                } else {
+                    // Dropped images fall back to their alt text
+                    None
+                }
+            }
+            Event::End(TagEnd::Image) => open_images
+                .pop()
+                .unwrap_or(false)
+                .then_some(Event::End(TagEnd::Image)),
+
+            event => Some(event),
+        }
+    });
+
+    let mut output = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut output, events);
+    output
+}
+
+fn demote(level: HeadingLevel) -> HeadingLevel {
+    HeadingLevel::try_from((level as usize + HEADING_DEMOTION).min(6)).unwrap_or(HeadingLevel::H6)
+}
+
+fn is_safe_url(url: &str, allow_mailto: bool) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    url.starts_with("https://")
+        || url.starts_with("http://")
+        || (allow_mailto && url.starts_with("mailto:"))
+}
+
+fn escape_attr(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_description;
+
+    #[test]
+    fn script_tags_are_escaped() {
+        let html = render_description("<script>alert(1)</script>");
+        assert!(!html.contains("<script"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn inline_html_is_escaped() {
+        let html = render_description("Hi <img src=x onerror=alert(1)> there");
+        assert!(!html.contains("<img"));
+        assert!(html.contains("&lt;img src=x onerror=alert(1)&gt;"));
+    }
+
+    #[test]
+    fn javascript_links_are_dropped() {
+        let html = render_description("[click](javascript:alert(1))");
+        assert!(!html.contains("href"));
+        assert!(!html.contains("javascript:"));
+        assert!(html.contains("click"));
+    }
+
+    #[test]
+    fn unsafe_autolinks_are_dropped() {
+        let html = render_description("<javascript:alert(1)>");
+        assert!(!html.contains("href"));
+
+        let html = render_description("<data:text/html,hi>");
+        assert!(!html.contains("href"));
+    }
+
+    #[test]
+    fn safe_links_get_nofollow_noopener() {
+        let html = render_description("[site](https://example.com) and <https://example.org>");
+        assert!(html.contains(r#"<a href="https://example.com" rel="nofollow noopener">site</a>"#));
+        assert!(html.contains(r#"<a href="https://example.org" rel="nofollow noopener">"#));
+    }
+
+    #[test]
+    fn email_autolinks_become_mailto() {
+        let html = render_description("<teacher@example.com>");
+        assert!(html.contains(r#"href="mailto:teacher@example.com" rel="nofollow noopener""#));
+    }
+
+    #[test]
+    fn link_attributes_are_escaped() {
+        let html = render_description(r#"[x](https://example.com/"onmouseover="alert(1))"#);
+        assert!(!html.contains(r#""onmouseover"#));
+        assert!(html.contains(r#"href="https://example.com/&quot;onmouseover=&quot;alert(1)""#));
+    }
+
+    #[test]
+    fn headings_are_demoted() {
+        let html = render_description("# Title\n\n### Section\n\n###### Small");
+        assert!(html.contains("<h4>Title</h4>"));
+        assert!(html.contains("<h6>Section</h6>"));
+        assert!(html.contains("<h6>Small</h6>"));
+        assert!(!html.contains("<h1>"));
+    }
+
+    #[test]
+    fn only_one_image_is_kept() {
+        let html = render_description(
+            "![first](https://example.com/a.png) ![second](https://example.com/b.png)",
+        );
+        assert_eq!(html.matches("<img").count(), 1);
+        assert!(html.contains(r#"src="https://example.com/a.png""#));
+        assert!(html.contains("second"));
+    }
+
+    #[test]
+    fn unsafe_images_are_dropped() {
+        let html = render_description("![pic](javascript:alert(1))");
+        assert!(!html.contains("<img"));
+        assert!(html.contains("pic"));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::posts::fetch_posts_by_ids;
+use crate::structs::{MatchQuery, PostMatch, PostType};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use http::StatusCode;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tower_sessions::Session;
+
+const DEFAULT_MATCHES: i64 = 10;
+const MAX_MATCHES: i64 = 50;
+
+struct MatchRow {
+    id: i32,
+    shared: Vec<String>,
+    pin_level: i32,
+    age_days: f64,
+    score: f64,
+}
+
+impl MatchRow {
+    fn reasons(&self) -> Vec<String> {
+        let mut reasons = vec![format!(
+            "Shares {} {}: {}",
+            self.shared.len(),
+            if self.shared.len() == 1 {
+                "category"
+            } else {
+                "categories"
+            },
+            self.shared.join(", ")
+        )];
+
+        match self.pin_level {
+            3 => reasons.push("Same pin code".to_string()),
+            2 => reasons.push("Nearby pin code (same sorting district)".to_string()),
+            1 => reasons.push("Pin code in the same postal region".to_string()),
+            _ => {}
+        }
+
+        match self.age_days {
+            d if d < 1.0 => reasons.push("Posted today".to_string()),
+            d if d < 7.0 => reasons.push("Posted this week".to_string()),
+            d if d < 30.0 => reasons.push("Posted this month".to_string()),
+            _ => {}
+        }
+
+        reasons
+    }
+}
+
+// Ranks open posts of the opposite type against the given post. The score weighs:
+//   60% share of the post's categories the candidate covers,
+//   25% pin-code proximity (same pin > same 3-digit district > same 2-digit region),
+//   15% recency, halving after 30 days.
+pub async fn list_matches(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Query(query): Query<MatchQuery>,
+) -> Result<Json<Vec<PostMatch>>, AppError> {
+    let _user_id = get_my_user_id(session).await?.0;
+
+    let source = sqlx::query!(
+        "SELECT user_id, post_type, categories, pin_code FROM posts WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found.", id),
+        )
+    })?;
+
+    let wanted_type = match PostType::from(source.post_type.as_str()) {
+        PostType::Offer => PostType::Request,
+        PostType::Request => PostType::Offer,
+    };
+    let pin_code = source
+        .pin_code
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+    let limit = query.limit.unwrap_or(DEFAULT_MATCHES).clamp(1, MAX_MATCHES);
+
+    let rows = sqlx::query_as!(
+        MatchRow,
+        r#"SELECT p.id,
+                m.shared as "shared!", m.pin_level as "pin_level!", m.age_days as "age_days!", s.score as "score!"
+         FROM posts p
+         CROSS JOIN LATERAL (SELECT
+                ARRAY(SELECT c FROM unnest(p.categories) c WHERE c = ANY($2)) AS shared,
+                CASE
+                    WHEN $3::TEXT IS NULL OR p.pin_code IS NULL THEN 0
+                    WHEN p.pin_code = $3 THEN 3
+                    WHEN left(p.pin_code, 3) = left($3, 3) THEN 2
+                    WHEN left(p.pin_code, 2) = left($3, 2) THEN 1
+                    ELSE 0
+                END AS pin_level,
+                EXTRACT(EPOCH FROM NOW() - p.created_at)::FLOAT8 / 86400 AS age_days) m
+         CROSS JOIN LATERAL (SELECT
+                (0.60 * cardinality(m.shared) / GREATEST(cardinality($2::TEXT[]), 1)
+                 + 0.25 * m.pin_level / 3.0
+                 + 0.15 / (1.0 + m.age_days / 30.0))::FLOAT8 AS score) s
+         WHERE p.deleted_at IS NULL
+           AND p.status = 'open'
+           AND p.post_type = $1
+           AND p.user_id <> $4
+           AND p.categories && $2
+         ORDER BY s.score DESC, p.id DESC
+         LIMIT $5"#,
+        wanted_type.to_string(),
+        &source.categories,
+        pin_code,
+        source.user_id,
+        limit
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+    let mut ranked: HashMap<i32, MatchRow> = rows.into_iter().map(|row| (row.id, row)).collect();
+
+    let matches = fetch_posts_by_ids(&pool, &ids, None)
+        .await?
+        .into_iter()
+        .filter_map(|post| {
+            let row = ranked.remove(&post.id)?;
+            Some(PostMatch {
+                score: (row.score * 1000.0).round() / 1000.0,
+                reasons: row.reasons(),
+                post,
+            })
+        })
+        .collect();
+
+    Ok(Json(matches))
+}
@@ -0,0 +1,416 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::pagination::encode_cursor;
+use crate::structs::{
+    Conversation, Message, NewConversation, NewMessage, Page, PageParams, UnreadCount,
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use http::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use tower_sessions::Session;
+
+const MAX_MESSAGE_LENGTH: usize = 4000;
+
+struct ConversationRow {
+    id: i32,
+    other_user_id: i32,
+    other_user_name: Option<String>,
+    other_profile_picture: Option<String>,
+    post_id: Option<i32>,
+    post_description: Option<String>,
+    last_message_id: Option<i32>,
+    last_sender_id: Option<i32>,
+    last_body: Option<String>,
+    last_created_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_read_at: Option<chrono::DateTime<chrono::Utc>>,
+    unread_count: i64,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_message_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<ConversationRow> for Conversation {
+    fn from(row: ConversationRow) -> Self {
+        let last_message = match (
+            row.last_message_id,
+            row.last_sender_id,
+            row.last_body,
+            row.last_created_at,
+        ) {
+            (Some(id), Some(sender_id), Some(body), Some(created_at)) => Some(Message {
+                id,
+                conversation_id: row.id,
+                sender_id,
+                body,
+                created_at,
+                read_at: row.last_read_at,
+            }),
+            _ => None,
+        };
+
+        Conversation {
+            id: row.id,
+            other_user_id: row.other_user_id,
+            other_user_name: row.other_user_name,
+            other_profile_picture: row.other_profile_picture,
+            post_id: row.post_id,
+            post_description: row.post_description,
+            last_message,
+            unread_count: row.unread_count,
+            created_at: row.created_at,
+            last_message_at: row.last_message_at,
+        }
+    }
+}
+
+fn conversation_not_found(id: i32) -> AppError {
+    AppError::HttpError(
+        StatusCode::NOT_FOUND,
+        anyhow::anyhow!("Conversation with id {} not found.", id),
+    )
+}
+
+fn validate_body(body: &str) -> Result<&str, AppError> {
+    let body = body.trim();
+    let problem = if body.is_empty() {
+        "body: must not be empty".to_string()
+    } else if body.chars().count() > MAX_MESSAGE_LENGTH {
+        format!("body: at most {} characters", MAX_MESSAGE_LENGTH)
+    } else {
+        return Ok(body);
+    };
+
+    Err(AppError::HttpError(
+        StatusCode::BAD_REQUEST,
+        anyhow::anyhow!("Invalid fields: {}", problem),
+    ))
+}
+
+// Conversations `user_id` takes part in, most recently active first.
+// `conversation_id` narrows it to one; only participants ever get a row back.
+async fn fetch_conversation_rows(
+    pool: &PgPool,
+    user_id: i32,
+    conversation_id: Option<i32>,
+    after: Option<(chrono::DateTime<chrono::Utc>, i32)>,
+    limit: i64,
+) -> Result<Vec<ConversationRow>, AppError> {
+    let (after_time, after_id) = after.unzip();
+
+    let rows = sqlx::query_as!(
+        ConversationRow,
+        r#"SELECT c.id, other.id as other_user_id, other.name as "other_user_name?", other.profile_picture as "other_profile_picture?",
+                c.post_id, p.description as "post_description?",
+                lm.id as "last_message_id?", lm.sender_id as "last_sender_id?", lm.body as "last_body?", lm.created_at as "last_created_at?", lm.read_at as "last_read_at?",
+                (SELECT COUNT(*) FROM messages m WHERE m.conversation_id = c.id AND m.sender_id <> $1 AND m.read_at IS NULL) as "unread_count!",
+                c.created_at, c.last_message_at
+         FROM conversations c
+         JOIN users other ON other.id = CASE WHEN c.user_a_id = $1 THEN c.user_b_id ELSE c.user_a_id END
+         LEFT JOIN posts p ON p.id = c.post_id
+         LEFT JOIN LATERAL (SELECT m.id, m.sender_id, m.body, m.created_at, m.read_at FROM messages m
+                            WHERE m.conversation_id = c.id ORDER BY m.id DESC LIMIT 1) lm ON TRUE
+         WHERE (c.user_a_id = $1 OR c.user_b_id = $1)
+           AND ($2::INT4 IS NULL OR c.id = $2)
+           AND ($3::TIMESTAMPTZ IS NULL OR (c.last_message_at, c.id) < ($3, $4::INT4))
+         ORDER BY c.last_message_at DESC, c.id DESC
+         LIMIT $5"#,
+        user_id,
+        conversation_id,
+        after_time,
+        after_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+async fn fetch_conversation(
+    pool: &PgPool,
+    id: i32,
+    user_id: i32,
+) -> Result<Conversation, AppError> {
+    fetch_conversation_rows(pool, user_id, Some(id), None, 1)
+        .await?
+        .into_iter()
+        .next()
+        .map(Conversation::from)
+        .ok_or_else(|| conversation_not_found(id))
+}
+
+// Locks the conversation for the rest of the transaction, failing unless `user_id` is in it
+async fn lock_conversation(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    user_id: i32,
+) -> Result<(), AppError> {
+    sqlx::query_scalar!(
+        "SELECT id FROM conversations WHERE id = $1 AND (user_a_id = $2 OR user_b_id = $2) FOR UPDATE",
+        id,
+        user_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| conversation_not_found(id))?;
+
+    Ok(())
+}
+
+async fn insert_message(
+    tx: &mut Transaction<'_, Postgres>,
+    conversation_id: i32,
+    sender_id: i32,
+    body: &str,
+) -> Result<Message, AppError> {
+    let message = sqlx::query_as!(
+        Message,
+        "INSERT INTO messages (conversation_id, sender_id, body) VALUES ($1, $2, $3)
+         RETURNING id, conversation_id, sender_id, body, created_at, read_at",
+        conversation_id,
+        sender_id,
+        body
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE conversations SET last_message_at = $2 WHERE id = $1",
+        conversation_id,
+        message.created_at
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(message)
+}
+
+pub async fn list_conversations(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Conversation>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let limit = params.limit();
+    let after = params.after::<(chrono::DateTime<chrono::Utc>, i32)>()?;
+
+    let rows = fetch_conversation_rows(&pool, user_id, None, after, limit + 1).await?;
+    let conversations = rows.into_iter().map(Conversation::from).collect();
+
+    Ok(Json(Page::from_rows(conversations, limit, |c| {
+        encode_cursor(&(c.last_message_at, c.id))
+    })))
+}
+
+pub async fn get_conversation(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<Json<Conversation>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    Ok(Json(fetch_conversation(&pool, id, user_id).await?))
+}
+
+// Opens (or reuses) the caller's conversation with another user, optionally about a post
+// one of them wrote, and sends the first message when one is given
+pub async fn start_conversation(
+    State(pool): State<PgPool>,
+    session: Session,
+    Json(new_conversation): Json<NewConversation>,
+) -> Result<Json<Conversation>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+    let other_id = new_conversation.user_id;
+
+    let body = new_conversation
+        .message
+        .as_deref()
+        .map(validate_body)
+        .transpose()?;
+
+    if other_id == user_id {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("You cannot message yourself"),
+        ));
+    }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1) as "exists!""#,
+        other_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if !exists {
+        return Err(AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("User with id {} not found.", other_id),
+        ));
+    }
+
+    if let Some(post_id) = new_conversation.post_id {
+        let author_id = sqlx::query_scalar!(
+            "SELECT user_id FROM posts WHERE id = $1 AND deleted_at IS NULL",
+            post_id
+        )
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| {
+            AppError::HttpError(
+                StatusCode::NOT_FOUND,
+                anyhow::anyhow!("Post with id {} not found.", post_id),
+            )
+        })?;
+
+        if author_id != user_id && author_id != other_id {
+            return Err(AppError::HttpError(
+                StatusCode::BAD_REQUEST,
+                anyhow::anyhow!(
+                    "A conversation can only be about a post by one of its participants"
+                ),
+            ));
+        }
+    }
+
+    let (user_a_id, user_b_id) = (user_id.min(other_id), user_id.max(other_id));
+
+    let mut tx = pool.begin().await?;
+
+    // The pair and post are unique, so an existing conversation is picked up instead
+    sqlx::query!(
+        "INSERT INTO conversations (user_a_id, user_b_id, post_id) VALUES ($1, $2, $3)
+         ON CONFLICT (user_a_id, user_b_id, post_id) DO NOTHING",
+        user_a_id,
+        user_b_id,
+        new_conversation.post_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let id = sqlx::query_scalar!(
+        "SELECT id FROM conversations WHERE user_a_id = $1 AND user_b_id = $2 AND post_id IS NOT DISTINCT FROM $3 FOR UPDATE",
+        user_a_id,
+        user_b_id,
+        new_conversation.post_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if let Some(body) = body {
+        insert_message(&mut tx, id, user_id, body).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_conversation(&pool, id, user_id).await?))
+}
+
+// Messages in a conversation, newest first
+pub async fn list_messages(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Message>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let limit = params.limit();
+    let after_id = params.after::<i32>()?;
+
+    let member = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM conversations WHERE id = $1 AND (user_a_id = $2 OR user_b_id = $2)) as "exists!""#,
+        id,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if !member {
+        return Err(conversation_not_found(id));
+    }
+
+    let messages = sqlx::query_as!(
+        Message,
+        "SELECT id, conversation_id, sender_id, body, created_at, read_at FROM messages
+         WHERE conversation_id = $1 AND ($2::INT4 IS NULL OR id < $2)
+         ORDER BY id DESC
+         LIMIT $3",
+        id,
+        after_id,
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(Page::from_rows(messages, limit, |m| {
+        encode_cursor(&m.id)
+    })))
+}
+
+pub async fn send_message(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Json(new_message): Json<NewMessage>,
+) -> Result<Json<Message>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let body = validate_body(&new_message.body)?;
+
+    let mut tx = pool.begin().await?;
+
+    lock_conversation(&mut tx, id, user_id).await?;
+    let message = insert_message(&mut tx, id, user_id, body).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(message))
+}
+
+// Marks everything the other participant has sent so far as read
+pub async fn mark_conversation_read(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<Json<Conversation>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut tx = pool.begin().await?;
+
+    lock_conversation(&mut tx, id, user_id).await?;
+    sqlx::query!(
+        "UPDATE messages SET read_at = NOW() WHERE conversation_id = $1 AND sender_id <> $2 AND read_at IS NULL",
+        id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(fetch_conversation(&pool, id, user_id).await?))
+}
+
+pub async fn get_unread_count(
+    State(pool): State<PgPool>,
+    session: Session,
+) -> Result<Json<UnreadCount>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let counts = sqlx::query_as!(
+        UnreadCount,
+        r#"SELECT COUNT(DISTINCT m.conversation_id) as "conversations!", COUNT(*) as "messages!"
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE (c.user_a_id = $1 OR c.user_b_id = $1) AND m.sender_id <> $1 AND m.read_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(counts))
+}
@@ -0,0 +1,52 @@
+use crate::error::AppError;
+use crate::structs::{Page, PageParams};
+use base64::prelude::*;
+use http::StatusCode;
+use serde::{Serialize, de::DeserializeOwned};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+impl PageParams {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    // Cursors are opaque to clients; they carry the sort key of the last row seen
+    pub fn after<K: DeserializeOwned>(&self) -> Result<Option<K>, AppError> {
+        let Some(cursor) = self.after.as_deref().filter(|c| !c.is_empty()) else {
+            return Ok(None);
+        };
+
+        BASE64_URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .map(Some)
+            .ok_or_else(|| {
+                AppError::HttpError(StatusCode::BAD_REQUEST, anyhow::anyhow!("Invalid cursor"))
+            })
+    }
+}
+
+pub fn encode_cursor<K: Serialize>(key: &K) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(key).unwrap_or_default())
+}
+
+impl<T> Page<T> {
+    // Expects up to `limit + 1` rows; the extra row only signals that another page exists
+    pub fn from_rows(mut items: Vec<T>, limit: i64, cursor_of: impl Fn(&T) -> String) -> Self {
+        let has_more = items.len() as i64 > limit;
+        items.truncate(limit as usize);
+
+        let next_cursor = if has_more {
+            items.last().map(cursor_of)
+        } else {
+            None
+        };
+
+        Page { items, next_cursor }
+    }
+}
@@ -9,23 +9,23 @@ pub async fn add_partitioned_attribute(request: Request<Body>, next: Next) -> Re
     let mut modified_cookies = Vec::new();
 
     for (name, value) in headers.iter() {
-        if name.as_str().to_lowercase() == "set-cookie" {
-            if let Ok(cookie_str) = value.to_str() {
-                tracing::info!("Processing cookie: {}", cookie_str);
-                
-                if cookie_str.contains("SameSite=None") && cookie_str.contains("Secure") {
-                    if !cookie_str.contains("Partitioned") {
-                        let modified_cookie = format!("{}; Partitioned", cookie_str);
-                        tracing::info!("Modified cookie: {}", modified_cookie);
-                        modified_cookies.push(modified_cookie);
-                    } else {
-                        tracing::info!("Cookie already has Partitioned attribute");
-                        modified_cookies.push(cookie_str.to_string());
-                    }
+        if name.as_str().to_lowercase() == "set-cookie"
+            && let Ok(cookie_str) = value.to_str()
+        {
+            tracing::info!("Processing cookie: {}", cookie_str);
+            
+            if cookie_str.contains("SameSite=None") && cookie_str.contains("Secure") {
+                if !cookie_str.contains("Partitioned") {
+                    let modified_cookie = format!("{}; Partitioned", cookie_str);
+                    tracing::info!("Modified cookie: {}", modified_cookie);
+                    modified_cookies.push(modified_cookie);
                 } else {
-                    tracing::info!("Cookie doesn't match criteria (SameSite=None + Secure)");
+                    tracing::info!("Cookie already has Partitioned attribute");
                     modified_cookies.push(cookie_str.to_string());
                 }
+            } else {
+                tracing::info!("Cookie doesn't match criteria (SameSite=None + Secure)");
+                modified_cookies.push(cookie_str.to_string());
             }
         }
     }
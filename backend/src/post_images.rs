@@ -0,0 +1,155 @@
+use crate::cloudinary::{CloudinaryConfig, CloudinaryService, ImageFolder, UploadedImage};
+use crate::error::AppError;
+use http::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+
+pub const MAX_POST_IMAGES: usize = 6;
+// Images arrive base64-encoded inside the form, roughly 5 MB of image each plus the rest of the post
+pub const POST_BODY_LIMIT: usize = MAX_POST_IMAGES * 7 * 1024 * 1024;
+
+fn cloudinary() -> Result<CloudinaryService, AppError> {
+    let config = CloudinaryConfig::from_env()
+        .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(CloudinaryService::new(config))
+}
+
+pub fn check_image_count(count: usize) -> Result<(), AppError> {
+    if count > MAX_POST_IMAGES {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("A post can have at most {} images", MAX_POST_IMAGES),
+        ));
+    }
+    Ok(())
+}
+
+// Uploads happen before the post is written, so a failure part-way removes what already went up
+pub async fn upload_post_images(images: &[String]) -> Result<Vec<UploadedImage>, AppError> {
+    if images.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let cloudinary = cloudinary()?;
+    let mut uploaded = Vec::with_capacity(images.len());
+
+    for data in images {
+        let public_id = format!("post_{}", uuid::Uuid::new_v4());
+
+        match cloudinary
+            .upload_image(data, ImageFolder::Posts, Some(public_id))
+            .await
+        {
+            Ok(image) => uploaded.push(image),
+            Err(e) => {
+                tracing::error!("Cloudinary upload failed: {}", e);
+                discard_images(uploaded.into_iter().map(|i| i.public_id).collect()).await;
+                return Err(AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e));
+            }
+        }
+    }
+
+    Ok(uploaded)
+}
+
+// Best effort: a leftover asset only costs storage, so failures are logged rather than surfaced
+pub async fn discard_images(public_ids: Vec<String>) {
+    if public_ids.is_empty() {
+        return;
+    }
+
+    let cloudinary = match CloudinaryConfig::from_env() {
+        Ok(config) => CloudinaryService::new(config),
+        Err(e) => {
+            tracing::warn!("Cannot delete {} post images: {}", public_ids.len(), e);
+            return;
+        }
+    };
+
+    for public_id in public_ids {
+        if let Err(e) = cloudinary.delete_image(&public_id).await {
+            tracing::warn!("Failed to delete post image {}: {}", public_id, e);
+        }
+    }
+}
+
+// The post's current images, in display order
+pub async fn post_image_ids(pool: &PgPool, post_id: i32) -> Result<Vec<i32>, AppError> {
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM post_images WHERE post_id = $1 ORDER BY position",
+        post_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(ids)
+}
+
+// Makes the post's images exactly `keep` (existing ids, in order) followed by `uploaded`.
+// Returns the Cloudinary ids of dropped images so the caller can delete them once committed.
+pub async fn save_post_images(
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: i32,
+    keep: &[i32],
+    uploaded: &[UploadedImage],
+) -> Result<Vec<String>, AppError> {
+    let existing = sqlx::query!(
+        "SELECT id, public_id FROM post_images WHERE post_id = $1",
+        post_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for (i, id) in keep.iter().enumerate() {
+        let problem = if keep[..i].contains(id) {
+            "is listed more than once"
+        } else if !existing.iter().any(|image| image.id == *id) {
+            "is not attached to this post"
+        } else {
+            continue;
+        };
+
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Image {} {}", id, problem),
+        ));
+    }
+
+    let (removed_ids, removed_public_ids): (Vec<i32>, Vec<String>) = existing
+        .into_iter()
+        .filter(|image| !keep.contains(&image.id))
+        .map(|image| (image.id, image.public_id))
+        .unzip();
+
+    sqlx::query!("DELETE FROM post_images WHERE id = ANY($1)", &removed_ids)
+        .execute(&mut **tx)
+        .await?;
+
+    // The (post_id, position) constraint is deferred, so positions may collide mid-statement
+    sqlx::query!(
+        "UPDATE post_images SET position = k.ord - 1
+         FROM unnest($1::INT4[]) WITH ORDINALITY k(id, ord)
+         WHERE post_images.id = k.id",
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    let (urls, public_ids): (Vec<String>, Vec<String>) = uploaded
+        .iter()
+        .map(|image| (image.url.clone(), image.public_id.clone()))
+        .unzip();
+
+    sqlx::query!(
+        "INSERT INTO post_images (post_id, url, public_id, position)
+         SELECT $1, u.url, u.public_id, $4 + u.ord - 1
+         FROM unnest($2::TEXT[], $3::TEXT[]) WITH ORDINALITY u(url, public_id, ord)",
+        post_id,
+        &urls,
+        &public_ids,
+        keep.len() as i64
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(removed_public_ids)
+}
@@ -1,305 +1,732 @@
 use crate::auth::get_my_user_id;
+use crate::categories::validate_categories;
 use crate::error;
-use crate::structs::{DeleteResponse, NewPost, NewPostForm, Post, PostType};
+use crate::expiry::ExpiryConfig;
+use crate::gazetteer::{
+    normalize_pin_code, pin_code_problem, pin_location, resolve_origin, validate_pin_code,
+    validate_radius,
+};
+use crate::markdown::render_description;
+use crate::pagination::encode_cursor;
+use crate::post_images::{
+    check_image_count, discard_images, post_image_ids, save_post_images, upload_post_images,
+};
+use crate::revisions::record_revision;
+use crate::structs::{
+    CategoryMatch, DeleteResponse, NewPost, NewPostForm, Page, PageParams, Post, PostCounts,
+    PostFilter, PostImage, PostPatch, PostSort, PostStatus, PostType, RenewPost, SortParams,
+    StatusChange,
+};
 use axum::{
     Form, Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
 use error::AppError;
-use http::StatusCode;
-use sqlx::PgPool;
+use http::{HeaderMap, HeaderName, StatusCode, header};
+use sqlx::{PgPool, Postgres, Transaction, types::Json as JsonColumn};
 use tower_sessions::Session;
 
-pub async fn list_my_posts(
-    State(pool): State<PgPool>,
-    session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let user_id = get_my_user_id(session).await?.0;
-
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 ORDER BY p.id DESC",
-        user_id
-    )
-    .fetch_all(&pool)
-    .await?;
+struct PostRow {
+    id: i32,
+    description: String,
+    categories: Vec<String>,
+    user_id: i32,
+    post_type: String,
+    pin_code: Option<String>,
+    locality: Option<String>,
+    district: Option<String>,
+    state: Option<String>,
+    user_name: Option<String>,
+    profile_picture: Option<String>,
+    status: String,
+    version: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    images: JsonColumn<Vec<PostImage>>,
+    distance_km: Option<f64>,
+    bookmarked: Option<bool>,
+}
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
+impl From<PostRow> for Post {
+    fn from(row: PostRow) -> Self {
+        Post {
             id: row.id,
+            description_html: render_description(&row.description),
             description: row.description,
             categories: row.categories,
             user_id: row.user_id,
-            post_type: match row.post_type.as_str() {
-                "offer" => PostType::Offer,
-                "request" => PostType::Request,
-                _ => PostType::Request,
-            },
+            post_type: PostType::from(row.post_type.as_str()),
             pin_code: row.pin_code,
+            location: pin_location(row.locality, row.district, row.state),
             user_name: row.user_name,
             profile_picture: row.profile_picture,
-        })
-        .collect();
+            status: PostStatus::from(row.status.as_str()),
+            version: row.version,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            expires_at: row.expires_at,
+            deleted_at: row.deleted_at,
+            snippet: None,
+            distance_km: row.distance_km,
+            bookmarked: row.bookmarked,
+            images: Some(row.images.0),
+            new_images: Vec::new(),
+        }
+    }
+}
+
+impl PostFilter {
+    fn for_type(post_type: PostType) -> Self {
+        PostFilter {
+            post_type: Some(post_type),
+            ..Default::default()
+        }
+    }
+
+    // Community feeds only show open posts unless another status is asked for
+    fn community(self, post_type: Option<PostType>) -> Self {
+        PostFilter {
+            post_type: post_type.or(self.post_type),
+            status: self.status.or(Some(PostStatus::Open)),
+            ..self
+        }
+    }
 
-    Ok(Json(posts))
+    fn category_list(&self) -> Option<Vec<String>> {
+        let categories: Vec<String> = self
+            .categories
+            .as_deref()?
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+
+        if categories.is_empty() {
+            None
+        } else {
+            Some(categories)
+        }
+    }
 }
 
-pub async fn list_user_posts(
-    State(pool): State<PgPool>,
-    Path(userid): Path<i32>,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
+// Whose posts a listing covers, on top of the filter, and who is looking at them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostScope {
+    pub author_id: Option<i32>,
+    pub bookmarked_by: Option<i32>,
+    pub followed_by: Option<i32>, // Only posts by users this user follows
+    pub viewer_id: Option<i32>,   // Sets `bookmarked` on each post for this user
+}
+
+impl PostScope {
+    fn author(author_id: i32) -> Self {
+        PostScope {
+            author_id: Some(author_id),
+            ..Default::default()
+        }
+    }
+
+    fn viewer(viewer_id: i32) -> Self {
+        PostScope {
+            viewer_id: Some(viewer_id),
+            ..Default::default()
+        }
+    }
+
+    fn following(viewer_id: i32) -> Self {
+        PostScope {
+            followed_by: Some(viewer_id),
+            viewer_id: Some(viewer_id),
+            ..Default::default()
+        }
+    }
+}
+
+// Shared keyset query behind every listing route.
+// The scope and the filter narrow the listing when set; ties on the sort timestamp fall back to id.
+pub async fn fetch_post_page(
+    pool: &PgPool,
+    scope: PostScope,
+    filter: &PostFilter,
+    sort: PostSort,
+    params: &PageParams,
+) -> Result<Page<Post>, AppError> {
+    if let Some(near) = filter.near.as_deref().filter(|n| !n.trim().is_empty()) {
+        return fetch_nearby_page(pool, scope, filter, near, params).await;
+    }
+
+    let limit = params.limit();
+    let (after_time, after_id) = params
+        .after::<(chrono::DateTime<chrono::Utc>, i32)>()?
+        .unzip();
+    let post_type = filter.post_type.as_ref().map(|t| t.to_string());
+    let status = filter.status.map(|s| s.to_string());
+    let pin_code = filter
+        .pin_code
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+
+    // Only one of these is bound so the GIN index sees a plain && or @>
+    let categories = filter.category_list();
+    let (any_categories, all_categories) = match filter.category_match {
+        CategoryMatch::Any => (categories, None),
+        CategoryMatch::All => (None, categories),
+    };
+
+    // One statement per sort order, with the keyset and ORDER BY on the bare columns, so each
+    // page is a range scan of idx_posts_created_at or idx_posts_updated_at. With no cursor the
+    // bound is +/-infinity, which keeps the comparison usable as an index condition.
+    macro_rules! post_page_query {
+        ($keyset:literal, $order:literal) => {
+            sqlx::query_as!(
+                PostRow,
+                r#"SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, loc.locality as "locality?", loc.district as "district?", loc.state as "state?", u.name as user_name, u.profile_picture, p.status, p.version, p.created_at, p.updated_at, p.expires_at, p.deleted_at, 
+                COALESCE((SELECT json_agg(json_build_object('id', i.id, 'url', i.url, 'position', i.position) ORDER BY i.position) FROM post_images i WHERE i.post_id = p.id), '[]') as "images!: JsonColumn<Vec<PostImage>>", 
+                NULL::FLOAT8 as distance_km, 
+                CASE WHEN $11::INT4 IS NULL THEN NULL ELSE EXISTS(SELECT 1 FROM bookmarks bm WHERE bm.user_id = $11 AND bm.post_id = p.id) END as bookmarked 
+         FROM posts p 
+         LEFT JOIN users u ON p.user_id = u.id 
+         LEFT JOIN pin_codes loc ON loc.pin_code = p.pin_code 
+         WHERE p.deleted_at IS NULL 
+           AND ($1::INT4 IS NULL OR p.user_id = $1) 
+           AND ($2::TEXT IS NULL OR p.post_type = $2) 
+           AND ($3::TEXT IS NULL OR p.pin_code = $3) 
+           AND ($4::TEXT[] IS NULL OR p.categories && $4) 
+           AND ($5::TEXT[] IS NULL OR p.categories @> $5) 
+           AND ($9::TEXT IS NULL OR p.status = $9) 
+           AND ($10::INT4 IS NULL OR EXISTS(SELECT 1 FROM bookmarks bm WHERE bm.user_id = $10 AND bm.post_id = p.id)) 
+           AND ($12::INT4 IS NULL OR EXISTS(SELECT 1 FROM follows f WHERE f.follower_id = $12 AND f.followee_id = p.user_id)) 
+           AND "#
+                    + $keyset
+                    + " ORDER BY "
+                    + $order
+                    + " LIMIT $8",
+                scope.author_id,
+                post_type,
+                pin_code,
+                any_categories.as_deref(),
+                all_categories.as_deref(),
+                after_time,
+                after_id,
+                limit + 1,
+                status,
+                scope.bookmarked_by,
+                scope.viewer_id,
+                scope.followed_by
+            )
+            .fetch_all(pool)
+            .await?
+        };
+    }
+
+    let rows = match sort {
+        PostSort::Newest => post_page_query!(
+            "(p.created_at, p.id) < (COALESCE($6::TIMESTAMPTZ, 'infinity'), COALESCE($7::INT4, 0))",
+            "p.created_at DESC, p.id DESC"
+        ),
+        PostSort::Oldest => post_page_query!(
+            "(p.created_at, p.id) > (COALESCE($6::TIMESTAMPTZ, '-infinity'), COALESCE($7::INT4, 0))",
+            "p.created_at ASC, p.id ASC"
+        ),
+        PostSort::Updated => post_page_query!(
+            "(p.updated_at, p.id) < (COALESCE($6::TIMESTAMPTZ, 'infinity'), COALESCE($7::INT4, 0))",
+            "p.updated_at DESC, p.id DESC"
+        ),
+    };
+
+    let posts: Vec<Post> = rows.into_iter().map(Post::from).collect();
+
+    Ok(Page::from_rows(posts, limit, |post| {
+        let sort_time = match sort {
+            PostSort::Updated => post.updated_at,
+            PostSort::Newest | PostSort::Oldest => post.created_at,
+        };
+        encode_cursor(&(sort_time, post.id))
+    }))
+}
+
+// Variant of fetch_post_page for `near` filters: only posts whose pin code is in the
+// gazetteer and within the radius, nearest first. The sort parameter does not apply here.
+async fn fetch_nearby_page(
+    pool: &PgPool,
+    scope: PostScope,
+    filter: &PostFilter,
+    near: &str,
+    params: &PageParams,
+) -> Result<Page<Post>, AppError> {
+    let (latitude, longitude) = resolve_origin(pool, near).await?;
+    let radius_km = validate_radius(filter.radius_km)?;
+
+    let limit = params.limit();
+    let (after_distance, after_id) = params.after::<(f64, i32)>()?.unzip();
+    let post_type = filter.post_type.as_ref().map(|t| t.to_string());
+    let status = filter.status.map(|s| s.to_string());
+    let pin_code = filter
+        .pin_code
+        .as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+
+    let categories = filter.category_list();
+    let (any_categories, all_categories) = match filter.category_match {
+        CategoryMatch::Any => (categories, None),
+        CategoryMatch::All => (None, categories),
+    };
+
+    // The latitude band is a cheap prefilter before the haversine distance (1 degree ~ 111 km)
+    let rows = sqlx::query_as!(
+        PostRow,
+        r#"SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, loc.locality as "locality?", loc.district as "district?", loc.state as "state?", u.name as user_name, u.profile_picture, p.status, p.version, p.created_at, p.updated_at, p.expires_at, p.deleted_at, 
+                COALESCE((SELECT json_agg(json_build_object('id', i.id, 'url', i.url, 'position', i.position) ORDER BY i.position) FROM post_images i WHERE i.post_id = p.id), '[]') as "images!: JsonColumn<Vec<PostImage>>", 
+                d.distance_km as "distance_km?", 
+                CASE WHEN $14::INT4 IS NULL THEN NULL ELSE EXISTS(SELECT 1 FROM bookmarks bm WHERE bm.user_id = $14 AND bm.post_id = p.id) END as bookmarked 
          FROM posts p 
          LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 ORDER BY p.id DESC",
-        userid
+         JOIN pin_codes loc ON loc.pin_code = p.pin_code 
+         CROSS JOIN LATERAL (SELECT 6371.0 * 2 * asin(sqrt(
+                power(sin(radians(loc.latitude - $6::FLOAT8) / 2), 2)
+                + cos(radians($6)) * cos(radians(loc.latitude)) * power(sin(radians(loc.longitude - $7::FLOAT8) / 2), 2)
+            )) AS distance_km) d 
+         WHERE p.deleted_at IS NULL 
+           AND loc.latitude BETWEEN $6 - $8::FLOAT8 / 111.0 AND $6 + $8 / 111.0 
+           AND d.distance_km <= $8 
+           AND ($1::INT4 IS NULL OR p.user_id = $1) 
+           AND ($2::TEXT IS NULL OR p.post_type = $2) 
+           AND ($3::TEXT IS NULL OR p.pin_code = $3) 
+           AND ($4::TEXT[] IS NULL OR p.categories && $4) 
+           AND ($5::TEXT[] IS NULL OR p.categories @> $5) 
+           AND ($9::TEXT IS NULL OR p.status = $9) 
+           AND ($13::INT4 IS NULL OR EXISTS(SELECT 1 FROM bookmarks bm WHERE bm.user_id = $13 AND bm.post_id = p.id)) 
+           AND ($15::INT4 IS NULL OR EXISTS(SELECT 1 FROM follows f WHERE f.follower_id = $15 AND f.followee_id = p.user_id)) 
+           AND ($10::FLOAT8 IS NULL OR (d.distance_km, p.id) > ($10, $11::INT4)) 
+         ORDER BY d.distance_km ASC, p.id ASC 
+         LIMIT $12"#,
+        scope.author_id,
+        post_type,
+        pin_code,
+        any_categories.as_deref(),
+        all_categories.as_deref(),
+        latitude,
+        longitude,
+        radius_km,
+        status,
+        after_distance,
+        after_id,
+        limit + 1,
+        scope.bookmarked_by,
+        scope.viewer_id,
+        scope.followed_by
     )
-    .fetch_all(&pool)
+    .fetch_all(pool)
     .await?;
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: match row.post_type.as_str() {
-                "offer" => PostType::Offer,
-                "request" => PostType::Request,
-                _ => PostType::Request,
-            },
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
+    let posts: Vec<Post> = rows.into_iter().map(Post::from).collect();
 
-    Ok(Json(posts))
+    Ok(Page::from_rows(posts, limit, |post| {
+        encode_cursor(&(post.distance_km, post.id))
+    }))
 }
 
-pub async fn list_offers(
+async fn fetch_post(pool: &PgPool, id: i32) -> Result<Post, AppError> {
+    let row = sqlx::query_as!(
+        PostRow,
+        r#"SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, loc.locality as "locality?", loc.district as "district?", loc.state as "state?", u.name as user_name, u.profile_picture, p.status, p.version, p.created_at, p.updated_at, p.expires_at, p.deleted_at, 
+                COALESCE((SELECT json_agg(json_build_object('id', i.id, 'url', i.url, 'position', i.position) ORDER BY i.position) FROM post_images i WHERE i.post_id = p.id), '[]') as "images!: JsonColumn<Vec<PostImage>>", 
+                NULL::FLOAT8 as distance_km, NULL::BOOL as bookmarked 
+         FROM posts p 
+         LEFT JOIN users u ON p.user_id = u.id 
+         LEFT JOIN pin_codes loc ON loc.pin_code = p.pin_code 
+         WHERE p.id = $1 AND p.deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(Post::from).ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found.", id),
+        )
+    })
+}
+
+// Live posts with the given ids, in the order given, for listings that rank posts themselves
+pub async fn fetch_posts_by_ids(
+    pool: &PgPool,
+    ids: &[i32],
+    viewer_id: Option<i32>,
+) -> Result<Vec<Post>, AppError> {
+    let rows = sqlx::query_as!(
+        PostRow,
+        r#"SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, loc.locality as "locality?", loc.district as "district?", loc.state as "state?", u.name as user_name, u.profile_picture, p.status, p.version, p.created_at, p.updated_at, p.expires_at, p.deleted_at,
+                COALESCE((SELECT json_agg(json_build_object('id', i.id, 'url', i.url, 'position', i.position) ORDER BY i.position) FROM post_images i WHERE i.post_id = p.id), '[]') as "images!: JsonColumn<Vec<PostImage>>",
+                NULL::FLOAT8 as distance_km,
+                CASE WHEN $2::INT4 IS NULL THEN NULL ELSE EXISTS(SELECT 1 FROM bookmarks bm WHERE bm.user_id = $2 AND bm.post_id = p.id) END as bookmarked
+         FROM unnest($1::INT4[]) WITH ORDINALITY AS wanted(id, position)
+         JOIN posts p ON p.id = wanted.id
+         LEFT JOIN users u ON p.user_id = u.id
+         LEFT JOIN pin_codes loc ON loc.pin_code = p.pin_code
+         WHERE p.deleted_at IS NULL
+         ORDER BY wanted.position"#,
+        ids,
+        viewer_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Post::from).collect())
+}
+
+type TaggedPost = ([(HeaderName, String); 1], Json<Post>);
+
+// Single-post responses carry the version as a strong ETag for If-Match on later writes
+fn tagged(post: Post) -> TaggedPost {
+    (
+        [(header::ETAG, format!("\"{}\"", post.version))],
+        Json(post),
+    )
+}
+
+// No If-Match header means the client opted out of the check
+fn check_if_match(headers: &HeaderMap, version: i32) -> Result<(), AppError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+
+    let current = format!("\"{}\"", version);
+    let matches = if_match.to_str().is_ok_and(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == current)
+    });
+
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::HttpError(
+            StatusCode::PRECONDITION_FAILED,
+            anyhow::anyhow!(
+                "Post has been modified since version {}",
+                if_match.to_str().unwrap_or("?")
+            ),
+        ))
+    }
+}
+
+// Locks an owned, live post for the rest of the transaction and returns its version
+async fn lock_own_post(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    user_id: i32,
+) -> Result<Option<i32>, AppError> {
+    let version = sqlx::query_scalar!(
+        "SELECT version FROM posts WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL FOR UPDATE",
+        id,
+        user_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(version)
+}
+
+pub async fn get_post(
     State(pool): State<PgPool>,
     session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
+    Path(id): Path<i32>,
+) -> Result<TaggedPost, AppError> {
+    let _user_id = get_my_user_id(session).await?.0;
+
+    Ok(tagged(fetch_post(&pool, id).await?))
+}
+
+pub async fn list_my_posts(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
     let user_id = get_my_user_id(session).await?.0;
 
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 AND p.post_type = 'offer' ORDER BY p.id",
+    let page = fetch_post_page(
+        &pool,
+        PostScope::author(user_id),
+        &PostFilter::default(),
+        sorting.sort,
+        &params,
+    )
+    .await?;
+
+    Ok(Json(page))
+}
+
+pub async fn list_user_posts(
+    State(pool): State<PgPool>,
+    Path(userid): Path<i32>,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let page = fetch_post_page(
+        &pool,
+        PostScope::author(userid),
+        &PostFilter::default(),
+        sorting.sort,
+        &params,
+    )
+    .await?;
+
+    Ok(Json(page))
+}
+
+// Open community posts, for the login page, which cannot list posts before sign-in
+pub async fn community_stats(State(pool): State<PgPool>) -> Result<Json<PostCounts>, AppError> {
+    let counts = sqlx::query_as!(
+        PostCounts,
+        r#"SELECT COUNT(*) as "total!",
+                COUNT(*) FILTER (WHERE post_type = 'offer') as "offers!",
+                COUNT(*) FILTER (WHERE post_type = 'request') as "requests!"
+         FROM posts WHERE deleted_at IS NULL AND status = 'open'"#
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(counts))
+}
+
+// The user's posts outside the trash, whatever their status, for their profile
+pub async fn user_post_counts(pool: &PgPool, user_id: i32) -> Result<PostCounts, AppError> {
+    let counts = sqlx::query_as!(
+        PostCounts,
+        r#"SELECT COUNT(*) as "total!",
+                COUNT(*) FILTER (WHERE post_type = 'offer') as "offers!",
+                COUNT(*) FILTER (WHERE post_type = 'request') as "requests!"
+         FROM posts WHERE user_id = $1 AND deleted_at IS NULL"#,
         user_id
     )
-    .fetch_all(&pool)
+    .fetch_one(pool)
     .await?;
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Offer,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
+    Ok(counts)
+}
 
-    Ok(Json(posts))
+pub async fn list_offers(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let page = fetch_post_page(
+        &pool,
+        PostScope::author(user_id),
+        &PostFilter::for_type(PostType::Offer),
+        sorting.sort,
+        &params,
+    )
+    .await?;
+
+    Ok(Json(page))
 }
 
 pub async fn list_requests(
     State(pool): State<PgPool>,
     session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
     let user_id = get_my_user_id(session).await?.0;
 
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.user_id = $1 AND p.post_type = 'request' ORDER BY p.id",
-        user_id
+    let page = fetch_post_page(
+        &pool,
+        PostScope::author(user_id),
+        &PostFilter::for_type(PostType::Request),
+        sorting.sort,
+        &params,
     )
-    .fetch_all(&pool)
     .await?;
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Request,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(page))
 }
 
 pub async fn list_community_posts(
     State(pool): State<PgPool>,
     session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let _user_id = get_my_user_id(session).await?.0;
+    Query(filter): Query<PostFilter>,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
 
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         ORDER BY p.id DESC"
+    let filter = filter.community(None);
+
+    let page = fetch_post_page(
+        &pool,
+        PostScope::viewer(user_id),
+        &filter,
+        sorting.sort,
+        &params,
     )
-    .fetch_all(&pool)
     .await?;
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: match row.post_type.as_str() {
-                "offer" => PostType::Offer,
-                "request" => PostType::Request,
-                _ => PostType::Request,
-            },
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(page))
 }
 
 pub async fn list_community_offers(
     State(pool): State<PgPool>,
     session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let _user_id = get_my_user_id(session).await?.0;
+    Query(filter): Query<PostFilter>,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
 
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.post_type = 'offer' ORDER BY p.id DESC"
+    let filter = filter.community(Some(PostType::Offer));
+
+    let page = fetch_post_page(
+        &pool,
+        PostScope::viewer(user_id),
+        &filter,
+        sorting.sort,
+        &params,
     )
-    .fetch_all(&pool)
     .await?;
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Offer,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
-
-    Ok(Json(posts))
+    Ok(Json(page))
 }
 
 pub async fn list_community_requests(
     State(pool): State<PgPool>,
     session: Session,
-) -> Result<Json<Vec<Post>>, AppError> {
-    let _user_id = get_my_user_id(session).await?.0;
+    Query(filter): Query<PostFilter>,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
 
-    let rows = sqlx::query!(
-        "SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, u.name as user_name, u.profile_picture 
-         FROM posts p 
-         LEFT JOIN users u ON p.user_id = u.id 
-         WHERE p.post_type = 'request' ORDER BY p.id DESC"
+    let filter = filter.community(Some(PostType::Request));
+
+    let page = fetch_post_page(
+        &pool,
+        PostScope::viewer(user_id),
+        &filter,
+        sorting.sort,
+        &params,
     )
-    .fetch_all(&pool)
     .await?;
 
-    let posts: Vec<Post> = rows
-        .into_iter()
-        .map(|row| Post {
-            id: row.id,
-            description: row.description,
-            categories: row.categories,
-            user_id: row.user_id,
-            post_type: PostType::Request,
-            pin_code: row.pin_code,
-            user_name: row.user_name,
-            profile_picture: row.profile_picture,
-        })
-        .collect();
+    Ok(Json(page))
+}
+
+// Open posts from the users the session user follows
+pub async fn list_following_posts(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(filter): Query<PostFilter>,
+    Query(sorting): Query<SortParams>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let filter = filter.community(None);
 
-    Ok(Json(posts))
+    let page = fetch_post_page(
+        &pool,
+        PostScope::following(user_id),
+        &filter,
+        sorting.sort,
+        &params,
+    )
+    .await?;
+
+    Ok(Json(page))
 }
 
 pub async fn create_post(
     State(pool): State<PgPool>,
     session: Session,
     Form(form_data): Form<NewPostForm>,
-) -> Result<Json<Post>, AppError> {
+) -> Result<TaggedPost, AppError> {
     let user_id = get_my_user_id(session).await?.0;
     
     // Parse the categories JSON string
     let categories: Vec<String> = serde_json::from_str(&form_data.categories)
         .map_err(|e| AppError::HttpError(StatusCode::BAD_REQUEST, anyhow::anyhow!("Invalid categories format: {}", e)))?;
     
+    validate_categories(&pool, &categories).await?;
+
+    let images: Vec<String> = match form_data.images.as_deref() {
+        Some(images) => serde_json::from_str(images).map_err(|e| {
+            AppError::HttpError(
+                StatusCode::BAD_REQUEST,
+                anyhow::anyhow!("Invalid images format: {}", e),
+            )
+        })?,
+        None => Vec::new(),
+    };
+    check_image_count(images.len())?;
+
+    let pin_code = validate_pin_code(&pool, form_data.pin_code.as_deref()).await?;
+
+    let expires_at = match form_data.expires_at {
+        Some(expires_at) => Some(validate_expiry(expires_at)?),
+        None => ExpiryConfig::from_env()
+            .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .default_expiry(),
+    };
+
     let new_post = NewPost {
         description: form_data.description,
         categories,
         post_type: form_data.post_type,
-        pin_code: form_data.pin_code,
+        pin_code,
+        expires_at,
     };
     
     let post_type_str = new_post.post_type.to_string();
 
-    let row = sqlx::query!(
-        "INSERT INTO posts (description, categories, user_id, post_type, pin_code) 
-         VALUES ($1, $2, $3, $4, $5) 
-         RETURNING id, description, categories, user_id, post_type, pin_code",
-        new_post.description,
-        &new_post.categories,
-        user_id,
-        post_type_str,
-        new_post.pin_code
-    )
-    .fetch_one(&pool)
-    .await?;
+    let uploaded = upload_post_images(&images).await?;
+
+    let saved = async {
+        let mut tx = pool.begin().await?;
+
+        let id = sqlx::query_scalar!(
+            "INSERT INTO posts (description, categories, user_id, post_type, pin_code, expires_at) 
+             VALUES ($1, $2, $3, $4, $5, $6) 
+             RETURNING id",
+            new_post.description,
+            &new_post.categories,
+            user_id,
+            post_type_str,
+            new_post.pin_code,
+            new_post.expires_at
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        save_post_images(&mut tx, id, &[], &uploaded).await?;
+        tx.commit().await?;
 
-    let user = sqlx::query!("SELECT name, profile_picture FROM users WHERE id = $1", user_id)
-        .fetch_one(&pool)
-        .await
-        .ok();
-
-    let created_post = Post {
-        id: row.id,
-        description: row.description,
-        categories: row.categories,
-        user_id: row.user_id,
-        post_type: new_post.post_type,
-        pin_code: row.pin_code,
-        user_name: user.as_ref().and_then(|u| u.name.clone()),
-        profile_picture: user.as_ref().and_then(|u| u.profile_picture.clone()),
+        Ok::<_, AppError>(id)
+    }
+    .await;
+
+    let id = match saved {
+        Ok(id) => id,
+        Err(e) => {
+            discard_images(uploaded.into_iter().map(|i| i.public_id).collect()).await;
+            return Err(e);
+        }
     };
 
-    Ok(Json(created_post))
+    Ok(tagged(fetch_post(&pool, id).await?))
 }
 
+// Deleting only moves the post to the trash; trash.rs purges it after the retention window
 pub async fn delete_post(
     State(pool): State<PgPool>,
     session: Session,
@@ -308,7 +735,7 @@ pub async fn delete_post(
     let user_id = get_my_user_id(session).await?.0;
 
     let result = sqlx::query!(
-        "DELETE FROM posts WHERE id = $1 AND user_id = $2",
+        "UPDATE posts SET deleted_at = NOW(), version = version + 1 WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
         id,
         user_id
     )
@@ -319,7 +746,7 @@ pub async fn delete_post(
         Ok(Json(DeleteResponse {
             success: true,
             id,
-            message: format!("Post with id {} deleted successfully.", id),
+            message: format!("Post with id {} moved to trash.", id),
         }))
     } else {
         Err(AppError::HttpError(
@@ -329,32 +756,349 @@ pub async fn delete_post(
     }
 }
 
+pub async fn list_trash(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let limit = params.limit();
+    let (after_time, after_id) = params
+        .after::<(chrono::DateTime<chrono::Utc>, i32)>()?
+        .unzip();
+
+    let rows = sqlx::query_as!(
+        PostRow,
+        r#"SELECT p.id, p.description, p.categories, p.user_id, p.post_type, p.pin_code, loc.locality as "locality?", loc.district as "district?", loc.state as "state?", u.name as user_name, u.profile_picture, p.status, p.version, p.created_at, p.updated_at, p.expires_at, p.deleted_at, 
+                COALESCE((SELECT json_agg(json_build_object('id', i.id, 'url', i.url, 'position', i.position) ORDER BY i.position) FROM post_images i WHERE i.post_id = p.id), '[]') as "images!: JsonColumn<Vec<PostImage>>", 
+                NULL::FLOAT8 as distance_km, NULL::BOOL as bookmarked 
+         FROM posts p 
+         LEFT JOIN users u ON p.user_id = u.id 
+         LEFT JOIN pin_codes loc ON loc.pin_code = p.pin_code 
+         WHERE p.user_id = $1 AND p.deleted_at IS NOT NULL 
+           AND ($2::TIMESTAMPTZ IS NULL OR (p.deleted_at, p.id) < ($2, $3::INT4)) 
+         ORDER BY p.deleted_at DESC, p.id DESC 
+         LIMIT $4"#,
+        user_id,
+        after_time,
+        after_id,
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let posts: Vec<Post> = rows.into_iter().map(Post::from).collect();
+
+    Ok(Json(Page::from_rows(posts, limit, |post| {
+        encode_cursor(&(post.deleted_at, post.id))
+    })))
+}
+
+pub async fn restore_post(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+) -> Result<TaggedPost, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let restored = sqlx::query_scalar!(
+        "UPDATE posts SET deleted_at = NULL, version = version + 1 WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL RETURNING id",
+        id,
+        user_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    match restored {
+        Some(id) => Ok(tagged(fetch_post(&pool, id).await?)),
+        None => Err(AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found in trash.", id),
+        )),
+    }
+}
+
 pub async fn update_post(
     State(pool): State<PgPool>,
     session: Session,
+    headers: HeaderMap,
     Json(post): Json<Post>,
-) -> Result<Json<Post>, AppError> {
+) -> Result<TaggedPost, AppError> {
     let user_id = get_my_user_id(session).await?.0;
+    validate_categories(&pool, &post.categories).await?;
+
+    // Without an `images` list the current images stay, with any uploads appended after them
+    let keep: Vec<i32> = match &post.images {
+        Some(images) => images.iter().map(|image| image.id).collect(),
+        None => post_image_ids(&pool, post.id).await?,
+    };
+    check_image_count(keep.len() + post.new_images.len())?;
+    let pin_code = validate_pin_code(&pool, post.pin_code.as_deref()).await?;
+
     let post_type_str = post.post_type.to_string();
 
-    let result = sqlx::query!(
-        "UPDATE posts SET description = $1, categories = $2, post_type = $3, pin_code = $4 WHERE id = $5 AND user_id = $6", 
-        post.description,
-        &post.categories,
-        post_type_str,
-        post.pin_code,
-        post.id,
+    let uploaded = upload_post_images(&post.new_images).await?;
+
+    let saved = async {
+        let mut tx = pool.begin().await?;
+
+        let version = lock_own_post(&mut tx, post.id, user_id)
+            .await?
+            .ok_or_else(|| {
+                AppError::HttpError(
+                    StatusCode::NOT_FOUND,
+                    anyhow::anyhow!("Post with id {} not found for update.", post.id),
+                )
+            })?;
+
+        check_if_match(&headers, version)?;
+        record_revision(&mut tx, post.id, user_id).await?;
+
+        sqlx::query!(
+            "UPDATE posts SET description = $1, categories = $2, post_type = $3, pin_code = $4, updated_at = NOW(), version = version + 1 
+             WHERE id = $5", 
+            post.description,
+            &post.categories,
+            post_type_str,
+            pin_code,
+            post.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let removed = save_post_images(&mut tx, post.id, &keep, &uploaded).await?;
+        tx.commit().await?;
+
+        Ok::<_, AppError>(removed)
+    }
+    .await;
+
+    match saved {
+        Ok(removed) => discard_images(removed).await,
+        Err(e) => {
+            discard_images(uploaded.into_iter().map(|i| i.public_id).collect()).await;
+            return Err(e);
+        }
+    }
+
+    Ok(tagged(fetch_post(&pool, post.id).await?))
+}
+
+// Checks each field of a merge-patch on its own so clients get every problem in one response
+async fn validate_patch(pool: &PgPool, patch: &PostPatch) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+
+    match &patch.description {
+        Some(None) => errors.push("description: cannot be null".to_string()),
+        Some(Some(description)) if description.trim().is_empty() => {
+            errors.push("description: cannot be empty".to_string())
+        }
+        _ => {}
+    }
+
+    match &patch.categories {
+        Some(None) => errors.push("categories: cannot be null".to_string()),
+        Some(Some(categories)) if categories.is_empty() => {
+            errors.push("categories: at least one category is required".to_string())
+        }
+        Some(Some(categories)) => match validate_categories(pool, categories).await {
+            Ok(()) => {}
+            Err(AppError::HttpError(_, e)) => errors.push(format!("categories: {}", e)),
+            Err(e) => return Err(e),
+        },
+        None => {}
+    }
+
+    if let Some(None) = patch.post_type {
+        errors.push("post_type: cannot be null".to_string());
+    }
+
+    if let Some(Some(pin_code)) = &patch.pin_code
+        && let Some(pin_code) = normalize_pin_code(pin_code)
+        && let Some(problem) = pin_code_problem(pool, &pin_code).await?
+    {
+        errors.push(format!("pin_code: {}", problem));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Invalid fields: {}", errors.join("; ")),
+        ))
+    }
+}
+
+pub async fn patch_post(
+    State(pool): State<PgPool>,
+    session: Session,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    Json(patch): Json<PostPatch>,
+) -> Result<TaggedPost, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+    validate_patch(&pool, &patch).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let version = lock_own_post(&mut tx, id, user_id).await?.ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found for update.", id),
+        )
+    })?;
+
+    check_if_match(&headers, version)?;
+
+    let description = patch.description.flatten();
+    let categories = patch.categories.flatten();
+    let post_type = patch.post_type.flatten().map(|t| t.to_string());
+    let pin_code_given = patch.pin_code.is_some();
+    let pin_code = patch
+        .pin_code
+        .flatten()
+        .and_then(|p| normalize_pin_code(&p));
+
+    let changes_something =
+        description.is_some() || categories.is_some() || post_type.is_some() || pin_code_given;
+
+    if changes_something {
+        record_revision(&mut tx, id, user_id).await?;
+
+        sqlx::query!(
+            "UPDATE posts SET description = COALESCE($1, description), categories = COALESCE($2, categories), 
+                    post_type = COALESCE($3, post_type), pin_code = CASE WHEN $4 THEN $5 ELSE pin_code END, 
+                    updated_at = NOW(), version = version + 1 
+             WHERE id = $6",
+            description.map(|d| d.trim().to_string()),
+            categories.as_deref(),
+            post_type,
+            pin_code_given,
+            pin_code,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(tagged(fetch_post(&pool, id).await?))
+}
+
+impl PostStatus {
+    // Fulfilled and closed posts can be reopened; everything else moves forward.
+    // Expired posts come back through renew_post, which also pushes out the expiry date.
+    fn can_become(self, next: PostStatus) -> bool {
+        use PostStatus::*;
+
+        matches!(
+            (self, next),
+            (Open, InProgress | Fulfilled | Closed)
+                | (InProgress, Open | Fulfilled | Closed)
+                | (Fulfilled, Open | Closed)
+                | (Closed, Open)
+                | (Expired, Closed)
+        )
+    }
+}
+
+pub async fn change_post_status(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Json(change): Json<StatusChange>,
+) -> Result<TaggedPost, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut tx = pool.begin().await?;
+
+    let current = sqlx::query_scalar!(
+        "SELECT status FROM posts WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL FOR UPDATE",
+        id,
         user_id
     )
-    .execute(&pool)
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|status| PostStatus::from(status.as_str()))
+    .ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found for status change.", id),
+        )
+    })?;
+
+    if !current.can_become(change.status) {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!(
+                "Cannot change post status from {} to {}",
+                current,
+                change.status
+            ),
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE posts SET status = $1, updated_at = NOW(), version = version + 1 WHERE id = $2",
+        change.status.to_string(),
+        id
+    )
+    .execute(&mut *tx)
     .await?;
 
-    if result.rows_affected() > 0 {
-        Ok(Json(post))
-    } else {
-        Err(AppError::HttpError(
+    tx.commit().await?;
+
+    Ok(tagged(fetch_post(&pool, id).await?))
+}
+
+pub fn validate_expiry(
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<chrono::DateTime<chrono::Utc>, AppError> {
+    if expires_at <= chrono::Utc::now() {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Expiry date must be in the future"),
+        ));
+    }
+
+    Ok(expires_at)
+}
+
+pub async fn renew_post(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(id): Path<i32>,
+    Json(renewal): Json<RenewPost>,
+) -> Result<TaggedPost, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let expires_at = match renewal.expires_at {
+        Some(expires_at) => Some(validate_expiry(expires_at)?),
+        None => ExpiryConfig::from_env()
+            .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .default_expiry(),
+    };
+
+    // Renewing brings an expired post back into the feed
+    let renewed = sqlx::query_scalar!(
+        "UPDATE posts SET expires_at = $1, version = version + 1, status = CASE WHEN status = 'expired' THEN 'open' ELSE status END 
+         WHERE id = $2 AND user_id = $3 AND deleted_at IS NULL AND status IN ('open', 'in_progress', 'expired') 
+         RETURNING id",
+        expires_at,
+        id,
+        user_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    match renewed {
+        Some(id) => Ok(tagged(fetch_post(&pool, id).await?)),
+        None => Err(AppError::HttpError(
             StatusCode::NOT_FOUND,
-            anyhow::anyhow!("Post with id {} not found for update.", post.id),
-        ))
+            anyhow::anyhow!("No renewable post with id {} found.", id),
+        )),
     }
 }
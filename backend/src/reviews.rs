@@ -0,0 +1,137 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::structs::{BookingStatus, NewReview, RatingSummary, Review};
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use http::StatusCode;
+use sqlx::PgPool;
+use tower_sessions::Session;
+
+const MAX_REVIEW_LENGTH: usize = 2000;
+const PROFILE_REVIEWS: i64 = 20;
+
+// A review is tied to a completed booking; the caller rates the other participant
+pub async fn create_review(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(booking_id): Path<i32>,
+    Json(new_review): Json<NewReview>,
+) -> Result<Json<Review>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let mut errors = Vec::new();
+    if !(1..=5).contains(&new_review.rating) {
+        errors.push("rating: must be between 1 and 5".to_string());
+    }
+    if new_review.text.len() > MAX_REVIEW_LENGTH {
+        errors.push(format!("text: at most {} characters", MAX_REVIEW_LENGTH));
+    }
+    if !errors.is_empty() {
+        return Err(AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Invalid fields: {}", errors.join("; ")),
+        ));
+    }
+
+    let booking = sqlx::query!(
+        "SELECT learner_id, teacher_id, status FROM bookings
+         WHERE id = $1 AND (learner_id = $2 OR teacher_id = $2)",
+        booking_id,
+        user_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Booking with id {} not found.", booking_id),
+        )
+    })?;
+
+    if BookingStatus::from(booking.status.as_str()) != BookingStatus::Completed {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("Reviews can only be left once the session is completed"),
+        ));
+    }
+
+    let reviewee_id = if booking.learner_id == user_id {
+        booking.teacher_id
+    } else {
+        booking.learner_id
+    };
+
+    // The unique (booking_id, reviewer_id) pair makes a second attempt insert nothing
+    let id = sqlx::query_scalar!(
+        "INSERT INTO reviews (booking_id, reviewer_id, reviewee_id, rating, text)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (booking_id, reviewer_id) DO NOTHING
+         RETURNING id",
+        booking_id,
+        user_id,
+        reviewee_id,
+        new_review.rating,
+        new_review.text.trim()
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("You have already reviewed this booking"),
+        )
+    })?;
+
+    let review = sqlx::query_as!(
+        Review,
+        r#"SELECT r.id, r.booking_id, b.post_id, r.reviewer_id, u.name as "reviewer_name?", r.rating, r.text, r.created_at
+         FROM reviews r
+         JOIN bookings b ON b.id = r.booking_id
+         LEFT JOIN users u ON u.id = r.reviewer_id
+         WHERE r.id = $1"#,
+        id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Json(review))
+}
+
+// Aggregate rating and latest reviews received by a user, for their profile
+pub async fn user_reputation(
+    pool: &PgPool,
+    user_id: i32,
+) -> Result<(RatingSummary, Vec<Review>), AppError> {
+    let summary = sqlx::query!(
+        r#"SELECT AVG(rating)::FLOAT8 as average, COUNT(*) as "count!"
+         FROM reviews WHERE reviewee_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let reviews = sqlx::query_as!(
+        Review,
+        r#"SELECT r.id, r.booking_id, b.post_id, r.reviewer_id, u.name as "reviewer_name?", r.rating, r.text, r.created_at
+         FROM reviews r
+         JOIN bookings b ON b.id = r.booking_id
+         LEFT JOIN users u ON u.id = r.reviewer_id
+         WHERE r.reviewee_id = $1
+         ORDER BY r.created_at DESC, r.id DESC
+         LIMIT $2"#,
+        user_id,
+        PROFILE_REVIEWS
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok((
+        RatingSummary {
+            average: summary.average.map(|a| (a * 100.0).round() / 100.0),
+            count: summary.count,
+        },
+        reviews,
+    ))
+}
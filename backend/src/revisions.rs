@@ -0,0 +1,207 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::structs::{FieldChange, PostRevision, PostType, RevisionDiff, RevisionDiffQuery};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use http::StatusCode;
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use tower_sessions::Session;
+
+struct RevisionRow {
+    revision: i32,
+    description: String,
+    categories: Vec<String>,
+    post_type: String,
+    pin_code: Option<String>,
+    edited_at: chrono::DateTime<chrono::Utc>,
+    replaced_at: chrono::DateTime<chrono::Utc>,
+    replaced_by: Option<i32>,
+}
+
+impl RevisionRow {
+    fn into_revision(self, post_id: i32) -> PostRevision {
+        PostRevision {
+            post_id,
+            revision: self.revision,
+            description: self.description,
+            categories: self.categories,
+            post_type: PostType::from(self.post_type.as_str()),
+            pin_code: self.pin_code,
+            edited_at: self.edited_at,
+            replaced_at: Some(self.replaced_at),
+            replaced_by: self.replaced_by,
+        }
+    }
+}
+
+// Copies the post's current content into post_revisions before it gets overwritten.
+// Call it inside the transaction that performs the update, after locking the post row.
+pub async fn record_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: i32,
+    user_id: i32,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "INSERT INTO post_revisions (post_id, revision, description, categories, post_type, pin_code, edited_at, replaced_by) 
+         SELECT p.id, COALESCE((SELECT MAX(r.revision) FROM post_revisions r WHERE r.post_id = p.id), 0) + 1, 
+                p.description, p.categories, p.post_type, p.pin_code, p.updated_at, $2 
+         FROM posts p WHERE p.id = $1",
+        post_id,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// History is visible to the post's owner and to moderators
+async fn authorize_history(pool: &PgPool, post_id: i32, user_id: i32) -> Result<(), AppError> {
+    let access = sqlx::query!(
+        "SELECT p.user_id, u.is_moderator FROM posts p, users u WHERE p.id = $1 AND u.id = $2",
+        post_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match access {
+        Some(row) if row.user_id == user_id || row.is_moderator => Ok(()),
+        Some(_) => Err(AppError::HttpError(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("Only the owner or a moderator can view this post's history"),
+        )),
+        None => Err(AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Post with id {} not found.", post_id),
+        )),
+    }
+}
+
+pub async fn list_revisions(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(post_id): Path<i32>,
+) -> Result<Json<Vec<PostRevision>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+    authorize_history(&pool, post_id, user_id).await?;
+
+    let rows = sqlx::query_as!(
+        RevisionRow,
+        "SELECT revision, description, categories, post_type, pin_code, edited_at, replaced_at, replaced_by 
+         FROM post_revisions WHERE post_id = $1 ORDER BY revision DESC",
+        post_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let revisions = rows
+        .into_iter()
+        .map(|row| row.into_revision(post_id))
+        .collect();
+
+    Ok(Json(revisions))
+}
+
+// Loads a stored revision, or the live post when `revision` is None
+async fn load_version(
+    pool: &PgPool,
+    post_id: i32,
+    revision: Option<i32>,
+) -> Result<PostRevision, AppError> {
+    let version = match revision {
+        Some(revision) => sqlx::query_as!(
+            RevisionRow,
+            "SELECT revision, description, categories, post_type, pin_code, edited_at, replaced_at, replaced_by 
+             FROM post_revisions WHERE post_id = $1 AND revision = $2",
+            post_id,
+            revision
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.into_revision(post_id)),
+        None => sqlx::query!(
+            "SELECT description, categories, post_type, pin_code, updated_at, 
+                    (SELECT COUNT(*) FROM post_revisions r WHERE r.post_id = p.id)::INT4 as \"revisions!\" 
+             FROM posts p WHERE p.id = $1",
+            post_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| PostRevision {
+            post_id,
+            revision: row.revisions + 1,
+            description: row.description,
+            categories: row.categories,
+            post_type: PostType::from(row.post_type.as_str()),
+            pin_code: row.pin_code,
+            edited_at: row.updated_at,
+            replaced_at: None,
+            replaced_by: None,
+        }),
+    };
+
+    version.ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Revision not found for post {}.", post_id),
+        )
+    })
+}
+
+pub async fn diff_revisions(
+    State(pool): State<PgPool>,
+    session: Session,
+    Path(post_id): Path<i32>,
+    Query(query): Query<RevisionDiffQuery>,
+) -> Result<Json<RevisionDiff>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+    authorize_history(&pool, post_id, user_id).await?;
+
+    let from = load_version(&pool, post_id, Some(query.from)).await?;
+    let to = load_version(&pool, post_id, query.to).await?;
+
+    let mut changes = Vec::new();
+
+    if from.description != to.description {
+        changes.push(FieldChange {
+            field: "description".to_string(),
+            before: json!(from.description),
+            after: json!(to.description),
+        });
+    }
+
+    if from.categories != to.categories {
+        changes.push(FieldChange {
+            field: "categories".to_string(),
+            before: json!(from.categories),
+            after: json!(to.categories),
+        });
+    }
+
+    if from.post_type != to.post_type {
+        changes.push(FieldChange {
+            field: "post_type".to_string(),
+            before: json!(from.post_type),
+            after: json!(to.post_type),
+        });
+    }
+
+    if from.pin_code != to.pin_code {
+        changes.push(FieldChange {
+            field: "pin_code".to_string(),
+            before: json!(from.pin_code),
+            after: json!(to.pin_code),
+        });
+    }
+
+    Ok(Json(RevisionDiff {
+        post_id,
+        from: from.revision,
+        to: to.revision,
+        changes,
+    }))
+}
@@ -0,0 +1,127 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::pagination::encode_cursor;
+use crate::posts::fetch_posts_by_ids;
+use crate::structs::{Page, PageParams, Post, SearchQuery};
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use http::StatusCode;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tower_sessions::Session;
+
+struct SearchRow {
+    id: i32,
+    snippet: String,
+    rank: f32,
+}
+
+// Turns user input into `to_tsquery` syntax:
+// bare words are AND-ed, "quoted words" must appear as a phrase, and a trailing * marks a prefix.
+fn build_tsquery(q: &str) -> Option<String> {
+    let mut clauses = Vec::new();
+
+    for (i, segment) in q.split('"').enumerate() {
+        let terms: Vec<String> = segment
+            .split_whitespace()
+            .filter_map(sanitize_term)
+            .collect();
+
+        if terms.is_empty() {
+            continue;
+        }
+
+        // Odd segments sit between a pair of quotes
+        if i % 2 == 1 {
+            clauses.push(format!("({})", terms.join(" <-> ")));
+        } else {
+            clauses.extend(terms);
+        }
+    }
+
+    if clauses.is_empty() {
+        None
+    } else {
+        Some(clauses.join(" & "))
+    }
+}
+
+fn sanitize_term(raw: &str) -> Option<String> {
+    let term: String = raw.chars().filter(|c| c.is_alphanumeric()).collect();
+
+    if term.is_empty() {
+        None
+    } else if raw.ends_with('*') {
+        Some(format!("{}:*", term))
+    } else {
+        Some(term)
+    }
+}
+
+pub async fn search_community_posts(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(search): Query<SearchQuery>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<Post>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let tsquery = build_tsquery(&search.q).ok_or_else(|| {
+        AppError::HttpError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Search query must contain at least one word"),
+        )
+    })?;
+
+    let limit = params.limit();
+    let (after_rank, after_id) = params.after::<(f32, i32)>()?.unzip();
+
+    // Descriptions are escaped before highlighting so only the <mark> tags are markup
+    let rows = sqlx::query_as!(
+        SearchRow,
+        r#"SELECT p.id,
+                ts_headline('english',
+                    replace(replace(replace(p.description, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'),
+                    query, 'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30, MinWords=10') as "snippet!",
+                ts_rank(p.search_vector, query) as "rank!"
+         FROM posts p
+         CROSS JOIN to_tsquery('english', $1) query
+         WHERE p.search_vector @@ query
+           AND p.status = 'open'
+           AND p.deleted_at IS NULL
+           AND ($2::REAL IS NULL OR (ts_rank(p.search_vector, query), p.id) < ($2, $3::INT4))
+         ORDER BY ts_rank(p.search_vector, query) DESC, p.id DESC
+         LIMIT $4"#,
+        tsquery,
+        after_rank,
+        after_id,
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let page = Page::from_rows(rows, limit, |row| encode_cursor(&(row.rank, row.id)));
+
+    let ids: Vec<i32> = page.items.iter().map(|row| row.id).collect();
+    let mut snippets: HashMap<i32, String> = page
+        .items
+        .into_iter()
+        .map(|row| (row.id, row.snippet))
+        .collect();
+
+    let items = fetch_posts_by_ids(&pool, &ids, Some(user_id))
+        .await?
+        .into_iter()
+        .map(|mut post| {
+            post.snippet = snippets.remove(&post.id);
+            post
+        })
+        .collect();
+
+    Ok(Json(Page {
+        items,
+        next_cursor: page.next_cursor,
+    }))
+}
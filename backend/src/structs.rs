@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -16,16 +16,104 @@ impl std::fmt::Display for PostType {
     }
 }
 
+impl From<&str> for PostType {
+    fn from(value: &str) -> Self {
+        match value {
+            "offer" => PostType::Offer,
+            "request" => PostType::Request,
+            _ => PostType::Request,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PostStatus {
+    #[default]
+    Open,
+    InProgress, // Someone has taken it up
+    Fulfilled,
+    Closed,  // Withdrawn by the owner
+    Expired, // Archived by the expiry sweeper, see expiry.rs
+}
+
+impl std::fmt::Display for PostStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostStatus::Open => write!(f, "open"),
+            PostStatus::InProgress => write!(f, "in_progress"),
+            PostStatus::Fulfilled => write!(f, "fulfilled"),
+            PostStatus::Closed => write!(f, "closed"),
+            PostStatus::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+impl From<&str> for PostStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "open" => PostStatus::Open,
+            "in_progress" => PostStatus::InProgress,
+            "fulfilled" => PostStatus::Fulfilled,
+            "closed" => PostStatus::Closed,
+            "expired" => PostStatus::Expired,
+            _ => PostStatus::Open,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Post {
     pub id: i32,
-    pub description: String,
+    pub description: String, // Markdown source
+    #[serde(default)]
+    pub description_html: String, // Sanitised render of `description`, ignored on input
     pub categories: Vec<String>,
     pub user_id: i32,
     pub post_type: PostType,
     pub pin_code: Option<String>,
+    #[serde(default)]
+    pub location: Option<PinLocation>, // Resolved from the gazetteer, ignored on input
     pub user_name: Option<String>,
     pub profile_picture: Option<String>,
+    #[serde(default)]
+    pub status: PostStatus,
+    #[serde(default)]
+    pub version: i32, // Also sent as the ETag header on single-post responses
+    #[serde(default)]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>, // Only set on posts in the trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>, // Highlighted excerpt, only set on search results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>, // Only set on listings filtered with `near`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bookmarked: Option<bool>, // Only set on community listings, for the session user
+    #[serde(default)]
+    pub images: Option<Vec<PostImage>>, // On update, the images to keep, in display order; None leaves them as they are
+    #[serde(default, skip_serializing)]
+    pub new_images: Vec<String>, // Base64 uploads appended after `images` on update
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PinLocation {
+    pub locality: String,
+    pub district: String,
+    pub state: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostImage {
+    pub id: i32,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub position: i32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -34,6 +122,7 @@ pub struct NewPost {
     pub categories: Vec<String>,
     pub post_type: PostType,
     pub pin_code: Option<String>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,6 +131,183 @@ pub struct NewPostForm {
     pub categories: String, // JSON string that will be parsed to Vec<String>
     pub post_type: PostType,
     pub pin_code: Option<String>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>, // Defaults to now + POST_TTL_DAYS
+    pub images: Option<String>, // JSON array of base64 images, like categories
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PageParams {
+    pub limit: Option<i64>,
+    pub after: Option<String>, // Opaque cursor taken from a previous page's next_cursor
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PostCounts {
+    pub total: i64,
+    pub offers: i64,
+    pub requests: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Category {
+    pub name: String,
+    pub group_name: String, // e.g. "Technology & Programming"
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CategoryMatch {
+    #[default]
+    Any, // Post has at least one of the categories
+    All, // Post has every one of the categories
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PostFilter {
+    pub post_type: Option<PostType>,
+    pub categories: Option<String>, // Comma-separated, e.g. "Cooking,Gardening"
+    #[serde(default)]
+    pub category_match: CategoryMatch,
+    pub pin_code: Option<String>,
+    pub status: Option<PostStatus>, // Community listings default to open posts only
+    pub near: Option<String>,       // Pin code or "lat,lon"; results are then sorted by distance
+    pub radius_km: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostSort {
+    #[default]
+    Newest,
+    Oldest,
+    Updated, // Most recently edited first
+}
+
+impl std::fmt::Display for PostSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostSort::Newest => write!(f, "newest"),
+            PostSort::Oldest => write!(f, "oldest"),
+            PostSort::Updated => write!(f, "updated"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SortParams {
+    #[serde(default)]
+    pub sort: PostSort,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatusChange {
+    pub status: PostStatus,
+}
+
+// Lets `Option<Option<T>>` tell an absent field (None) apart from an explicit null (Some(None))
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// JSON merge-patch (RFC 7396) body for PATCH /posts/{id}; absent fields are left untouched
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PostPatch {
+    #[serde(default, deserialize_with = "present")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub categories: Option<Option<Vec<String>>>,
+    #[serde(default, deserialize_with = "present")]
+    pub post_type: Option<Option<PostType>>,
+    #[serde(default, deserialize_with = "present")]
+    pub pin_code: Option<Option<String>>, // null clears the pin code
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RenewPost {
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>, // Defaults to now + POST_TTL_DAYS
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostRevision {
+    pub post_id: i32,
+    pub revision: i32, // The live post counts as one past the latest stored revision
+    pub description: String,
+    pub categories: Vec<String>,
+    pub post_type: PostType,
+    pub pin_code: Option<String>,
+    pub edited_at: chrono::DateTime<chrono::Utc>,
+    pub replaced_at: Option<chrono::DateTime<chrono::Utc>>, // None for the live post
+    pub replaced_by: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevisionDiffQuery {
+    pub from: i32,
+    pub to: Option<i32>, // Defaults to the live post
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevisionDiff {
+    pub post_id: i32,
+    pub from: i32,
+    pub to: i32,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GeoPlace {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub display_name: String,
+    pub postcode: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReverseQuery {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GeoJsonQuery {
+    pub bbox: Option<String>, // "min_lon,min_lat,max_lon,max_lat", as Leaflet's toBBoxString()
+    pub zoom: Option<u8>,
+    pub post_type: Option<PostType>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MatchQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostMatch {
+    pub post: Post,
+    pub score: f64, // 0.0..=1.0, higher is a better fit
+    pub reasons: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -51,15 +317,207 @@ pub struct DeleteResponse {
     pub message: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferFormat {
+    #[default]
+    Json,
+    Csv, // Categories are joined with ";" in a single column
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TransferQuery {
+    pub format: Option<TransferFormat>, // Import falls back to the Content-Type
+}
+
+// One post in an export file; import reads the same shape and ignores id, status and timestamps
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostRecord {
+    #[serde(default)]
+    pub id: Option<i32>,
+    pub post_type: String,
+    pub description: String,
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub pin_code: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportOutcome {
+    Created,
+    Skipped, // Same type and description as an existing post or an earlier row
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportRowReport {
+    pub row: usize, // 1-based, not counting the CSV header
+    pub outcome: ImportOutcome,
+    pub id: Option<i32>,
+    pub messages: Vec<String>, // Why the row failed or was skipped
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportReport {
+    pub created: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BookingStatus {
+    Requested, // Waiting on the teacher
+    Proposed,  // The teacher offered other times, waiting on the learner
+    Accepted,
+    Declined,
+    Cancelled,
+    Completed,
+}
+
+impl std::fmt::Display for BookingStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookingStatus::Requested => write!(f, "requested"),
+            BookingStatus::Proposed => write!(f, "proposed"),
+            BookingStatus::Accepted => write!(f, "accepted"),
+            BookingStatus::Declined => write!(f, "declined"),
+            BookingStatus::Cancelled => write!(f, "cancelled"),
+            BookingStatus::Completed => write!(f, "completed"),
+        }
+    }
+}
+
+impl From<&str> for BookingStatus {
+    fn from(value: &str) -> Self {
+        match value {
+            "requested" => BookingStatus::Requested,
+            "proposed" => BookingStatus::Proposed,
+            "accepted" => BookingStatus::Accepted,
+            "declined" => BookingStatus::Declined,
+            "cancelled" => BookingStatus::Cancelled,
+            "completed" => BookingStatus::Completed,
+            _ => BookingStatus::Requested,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimeSlot {
+    pub starts_at: chrono::DateTime<chrono::Utc>,
+    pub ends_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookingSlot {
+    pub id: i32,
+    pub proposed_by: i32,
+    pub starts_at: chrono::DateTime<chrono::Utc>,
+    pub ends_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Booking {
+    pub id: i32,
+    pub post_id: Option<i32>, // None once the offer has been purged from the trash
+    pub post_description: String,
+    pub learner_id: i32,
+    pub learner_name: Option<String>,
+    pub teacher_id: i32, // Owner of the offer
+    pub teacher_name: Option<String>,
+    pub message: String,
+    pub status: BookingStatus,
+    pub starts_at: Option<chrono::DateTime<chrono::Utc>>, // Set once a slot is accepted
+    pub ends_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub slots: Vec<BookingSlot>, // The latest proposal; empty once accepted or closed
+    pub learner_confirmed_at: Option<chrono::DateTime<chrono::Utc>>, // Completion needs both confirmations
+    pub teacher_confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewBooking {
+    pub post_id: i32,
+    #[serde(default)]
+    pub message: String,
+    pub slots: Vec<TimeSlot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlotProposal {
+    pub slots: Vec<TimeSlot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlotChoice {
+    pub slot_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BookingScope {
+    #[default]
+    Upcoming, // Still being arranged, or accepted and not yet over; soonest first
+    Past, // Over, declined or cancelled; most recent first
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BookingRole {
+    Learner,
+    Teacher,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BookingQuery {
+    #[serde(default)]
+    pub scope: BookingScope,
+    pub role: Option<BookingRole>, // Both sides when absent
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct User {
+pub struct CreditBalance {
+    pub balance_hours: f64,
+    pub committed_hours: f64, // Accepted sessions the user is learning in, not yet settled
+    pub available_hours: f64, // What new bookings can still be accepted against
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CreditKind {
+    Opening,  // Starting balance from the bank
+    Exchange, // A completed booking
+}
+
+impl From<&str> for CreditKind {
+    fn from(value: &str) -> Self {
+        match value {
+            "opening" => CreditKind::Opening,
+            _ => CreditKind::Exchange,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreditTransaction {
     pub id: i32,
-    pub email: String,
-    pub password_hash: String,
+    pub kind: CreditKind,
+    pub booking_id: Option<i32>,
+    pub hours: f64,                   // Positive when received, negative when spent
+    pub counterparty_id: Option<i32>, // None for the bank
+    pub counterparty_name: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
-    pub pin_code: Option<String>,
-    pub name: Option<String>,
-    pub profile_picture: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -68,7 +526,98 @@ pub struct UserProfile {
     pub email: String,
     pub name: Option<String>,
     pub pin_code: Option<String>,
+    pub location: Option<PinLocation>,
     pub profile_picture: Option<String>,
+    #[serde(default)]
+    pub rating: RatingSummary,
+    #[serde(default)]
+    pub reviews: Vec<Review>, // Most recent first, see reviews.rs for how many
+    #[serde(default)]
+    pub follower_count: i64,
+    #[serde(default)]
+    pub following_count: i64,
+    #[serde(default)]
+    pub post_counts: PostCounts, // Posts outside the trash, so the profile needs no full listing
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RatingSummary {
+    pub average: Option<f64>, // None until the first review
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Review {
+    pub id: i32,
+    pub booking_id: i32,
+    pub post_id: Option<i32>,
+    pub reviewer_id: i32,
+    pub reviewer_name: Option<String>,
+    pub rating: i16, // 1 to 5 stars
+    pub text: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewReview {
+    pub rating: i16,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookmarkResponse {
+    pub post_id: i32,
+    pub bookmarked: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FollowResponse {
+    pub user_id: i32,
+    pub following: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Message {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub sender_id: i32,
+    pub body: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub read_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// A conversation as seen by one of its two participants
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Conversation {
+    pub id: i32,
+    pub other_user_id: i32,
+    pub other_user_name: Option<String>,
+    pub other_profile_picture: Option<String>,
+    pub post_id: Option<i32>,
+    pub post_description: Option<String>,
+    pub last_message: Option<Message>,
+    pub unread_count: i64, // Messages from the other user not yet marked read
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_message_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewConversation {
+    pub user_id: i32,
+    pub post_id: Option<i32>,
+    pub message: Option<String>, // Sent as the first message when given
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewMessage {
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnreadCount {
+    pub conversations: i64,
+    pub messages: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -0,0 +1,329 @@
+use crate::auth::get_my_user_id;
+use crate::error::AppError;
+use crate::pagination::encode_cursor;
+use crate::structs::{CreditBalance, CreditKind, CreditTransaction, Page, PageParams};
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use http::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use tower_sessions::Session;
+
+const DEFAULT_STARTING_HOURS: f64 = 2.0;
+
+#[derive(Debug, Clone)]
+pub struct TimeBankConfig {
+    pub starting_minutes: i32, // Credited to every new account by the bank
+}
+
+impl TimeBankConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let hours = match std::env::var("TIME_BANK_STARTING_HOURS") {
+            Ok(hours) => hours
+                .parse::<f64>()
+                .ok()
+                .filter(|h| *h >= 0.0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("TIME_BANK_STARTING_HOURS must be a non-negative number")
+                })?,
+            Err(_) => DEFAULT_STARTING_HOURS,
+        };
+
+        Ok(Self {
+            starting_minutes: (hours * 60.0).round() as i32,
+        })
+    }
+}
+
+fn hours(minutes: i64) -> f64 {
+    (minutes as f64 / 60.0 * 100.0).round() / 100.0
+}
+
+// Posts one balanced ledger transaction and applies it to the cached balances.
+// Callers lock the accounts involved first.
+async fn post_transaction(
+    tx: &mut Transaction<'_, Postgres>,
+    kind: CreditKind,
+    booking_id: Option<i32>,
+    from_account: i32,
+    to_account: i32,
+    minutes: i32,
+) -> Result<(), AppError> {
+    let kind = match kind {
+        CreditKind::Opening => "opening",
+        CreditKind::Exchange => "exchange",
+    };
+
+    let transaction_id = sqlx::query_scalar!(
+        "INSERT INTO credit_transactions (kind, booking_id) VALUES ($1, $2) RETURNING id",
+        kind,
+        booking_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO ledger_entries (transaction_id, account_id, amount_minutes) VALUES ($1, $2, $3), ($1, $4, $5)",
+        transaction_id,
+        from_account,
+        -minutes,
+        to_account,
+        minutes
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE credit_accounts SET balance_minutes = balance_minutes + CASE WHEN id = $1 THEN -$3::INT4 ELSE $3 END
+         WHERE id IN ($1, $2)",
+        from_account,
+        to_account,
+        minutes
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+// Gives a newly registered user an account holding the starting balance
+pub async fn open_account(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+) -> Result<(), AppError> {
+    let config = TimeBankConfig::from_env()
+        .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let account_id = sqlx::query_scalar!(
+        "INSERT INTO credit_accounts (user_id) VALUES ($1) RETURNING id",
+        user_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if config.starting_minutes > 0 {
+        let bank_id =
+            sqlx::query_scalar!("SELECT id FROM credit_accounts WHERE user_id IS NULL FOR UPDATE")
+                .fetch_one(&mut **tx)
+                .await?;
+
+        post_transaction(
+            tx,
+            CreditKind::Opening,
+            None,
+            bank_id,
+            account_id,
+            config.starting_minutes,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Minutes of accepted, unsettled sessions where the user is the learner
+async fn committed_minutes(
+    executor: impl sqlx::PgExecutor<'_>,
+    user_id: i32,
+    excluding_booking: Option<i32>,
+) -> Result<i64, AppError> {
+    let minutes = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(EXTRACT(EPOCH FROM ends_at - starts_at)::INT8 / 60), 0)::INT8 as "minutes!"
+         FROM bookings
+         WHERE learner_id = $1 AND status = 'accepted' AND ($2::INT4 IS NULL OR id <> $2)"#,
+        user_id,
+        excluding_booking
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(minutes)
+}
+
+fn session_minutes(
+    starts_at: chrono::DateTime<chrono::Utc>,
+    ends_at: chrono::DateTime<chrono::Utc>,
+) -> i32 {
+    (ends_at - starts_at).num_minutes() as i32
+}
+
+// Accepting a booking commits the learner's credits, so their balance must cover it
+// on top of the sessions they have already committed to
+pub async fn check_affordable(
+    tx: &mut Transaction<'_, Postgres>,
+    booking_id: i32,
+    learner_id: i32,
+    starts_at: chrono::DateTime<chrono::Utc>,
+    ends_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), AppError> {
+    let balance = sqlx::query_scalar!(
+        "SELECT balance_minutes FROM credit_accounts WHERE user_id = $1 FOR UPDATE",
+        learner_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .unwrap_or(0) as i64;
+
+    let available = balance - committed_minutes(&mut **tx, learner_id, Some(booking_id)).await?;
+    let cost = session_minutes(starts_at, ends_at) as i64;
+
+    if cost > available {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!(
+                "Not enough time credits: the learner has {} hours available and this session costs {}",
+                hours(available),
+                hours(cost)
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// Pays the teacher for a completed session out of the learner's account, as part of the
+// caller's transaction. The unique booking_id on credit_transactions stops a second payment.
+pub async fn settle_booking(
+    tx: &mut Transaction<'_, Postgres>,
+    booking_id: i32,
+) -> Result<(), AppError> {
+    let booking = sqlx::query!(
+        r#"SELECT learner_id, teacher_id, starts_at as "starts_at!", ends_at as "ends_at!" FROM bookings
+         WHERE id = $1 AND starts_at IS NOT NULL AND ends_at IS NOT NULL"#,
+        booking_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let settled = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM credit_transactions WHERE booking_id = $1) as "exists!""#,
+        booking_id
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if settled {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!("Booking {} has already been settled", booking_id),
+        ));
+    }
+
+    // Locked in id order so two settlements between the same pair cannot deadlock
+    let accounts = sqlx::query!(
+        "SELECT id, user_id, balance_minutes FROM credit_accounts WHERE user_id IN ($1, $2) ORDER BY id FOR UPDATE",
+        booking.learner_id,
+        booking.teacher_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let account_of = |user_id: i32| {
+        accounts
+            .iter()
+            .find(|a| a.user_id == Some(user_id))
+            .ok_or_else(|| {
+                AppError::Internal(anyhow::anyhow!("User {} has no credit account", user_id))
+            })
+    };
+    let learner = account_of(booking.learner_id)?;
+    let teacher = account_of(booking.teacher_id)?;
+    let cost = session_minutes(booking.starts_at, booking.ends_at);
+
+    if learner.balance_minutes < cost {
+        return Err(AppError::HttpError(
+            StatusCode::CONFLICT,
+            anyhow::anyhow!(
+                "Not enough time credits: the learner has {} hours and this session costs {}",
+                hours(learner.balance_minutes as i64),
+                hours(cost as i64)
+            ),
+        ));
+    }
+
+    post_transaction(
+        tx,
+        CreditKind::Exchange,
+        Some(booking_id),
+        learner.id,
+        teacher.id,
+        cost,
+    )
+    .await
+}
+
+pub async fn get_my_credits(
+    State(pool): State<PgPool>,
+    session: Session,
+) -> Result<Json<CreditBalance>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let balance = sqlx::query_scalar!(
+        "SELECT balance_minutes FROM credit_accounts WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .unwrap_or(0) as i64;
+
+    let committed = committed_minutes(&pool, user_id, None).await?;
+
+    Ok(Json(CreditBalance {
+        balance_hours: hours(balance),
+        committed_hours: hours(committed),
+        available_hours: hours(balance - committed),
+    }))
+}
+
+// The caller's side of each ledger transaction, newest first
+pub async fn list_my_credit_history(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(params): Query<PageParams>,
+) -> Result<Json<Page<CreditTransaction>>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let limit = params.limit();
+    let (after_time, after_id) = params
+        .after::<(chrono::DateTime<chrono::Utc>, i32)>()?
+        .unzip();
+
+    let rows = sqlx::query!(
+        r#"SELECT t.id, t.kind, t.booking_id, t.created_at, e.amount_minutes,
+                other.user_id as "counterparty_id?", u.name as "counterparty_name?"
+         FROM ledger_entries e
+         JOIN credit_accounts a ON a.id = e.account_id
+         JOIN credit_transactions t ON t.id = e.transaction_id
+         LEFT JOIN ledger_entries oe ON oe.transaction_id = t.id AND oe.account_id <> e.account_id
+         LEFT JOIN credit_accounts other ON other.id = oe.account_id
+         LEFT JOIN users u ON u.id = other.user_id
+         WHERE a.user_id = $1
+           AND ($2::TIMESTAMPTZ IS NULL OR (t.created_at, t.id) < ($2, $3::INT4))
+         ORDER BY t.created_at DESC, t.id DESC
+         LIMIT $4"#,
+        user_id,
+        after_time,
+        after_id,
+        limit + 1
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let transactions = rows
+        .into_iter()
+        .map(|row| CreditTransaction {
+            id: row.id,
+            kind: CreditKind::from(row.kind.as_str()),
+            booking_id: row.booking_id,
+            hours: hours(row.amount_minutes as i64),
+            counterparty_id: row.counterparty_id,
+            counterparty_name: row.counterparty_name,
+            created_at: row.created_at,
+        })
+        .collect();
+
+    Ok(Json(Page::from_rows(transactions, limit, |t| {
+        encode_cursor(&(t.created_at, t.id))
+    })))
+}
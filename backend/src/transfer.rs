@@ -0,0 +1,519 @@
+use crate::auth::get_my_user_id;
+use crate::categories::validate_categories;
+use crate::error::AppError;
+use crate::expiry::ExpiryConfig;
+use crate::gazetteer::{normalize_pin_code, pin_code_problem};
+use crate::posts::validate_expiry;
+use crate::structs::{
+    ImportOutcome, ImportReport, ImportRowReport, NewPost, PostRecord, PostType, TransferFormat,
+    TransferQuery,
+};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream};
+use http::{HeaderMap, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use tower_sessions::Session;
+
+const MAX_IMPORT_ROWS: usize = 1000;
+const CATEGORY_SEPARATOR: char = ';';
+
+// PostRecord flattened for spreadsheets, with the categories in one column
+#[derive(Serialize, Deserialize)]
+struct CsvPostRecord {
+    #[serde(default)]
+    id: Option<i32>,
+    post_type: String,
+    description: String,
+    categories: String,
+    #[serde(default)]
+    pin_code: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+const CSV_HEADERS: [&str; 9] = [
+    "id",
+    "post_type",
+    "description",
+    "categories",
+    "pin_code",
+    "status",
+    "expires_at",
+    "created_at",
+    "updated_at",
+];
+
+impl From<PostRecord> for CsvPostRecord {
+    fn from(record: PostRecord) -> Self {
+        Self {
+            id: record.id,
+            post_type: record.post_type,
+            description: record.description,
+            categories: record.categories.join(&format!("{} ", CATEGORY_SEPARATOR)),
+            pin_code: record.pin_code,
+            status: record.status,
+            expires_at: record.expires_at,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+impl From<CsvPostRecord> for PostRecord {
+    fn from(record: CsvPostRecord) -> Self {
+        Self {
+            id: record.id,
+            post_type: record.post_type,
+            description: record.description,
+            categories: record
+                .categories
+                .split(CATEGORY_SEPARATOR)
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect(),
+            pin_code: record.pin_code,
+            status: record.status,
+            expires_at: record.expires_at,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+fn csv_line(record: PostRecord) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer.serialize(CsvPostRecord::from(record))?;
+    Ok(writer.into_inner()?)
+}
+
+// Streams the caller's posts (not those in the trash) straight from the database cursor
+pub async fn export_posts(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(query): Query<TransferQuery>,
+) -> Result<Response, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+    let format = query.format.unwrap_or_default();
+
+    // The row stream borrows the pool, so it is drained in a task that outlives this handler
+    let (sender, receiver) = tokio::sync::mpsc::channel::<anyhow::Result<Vec<u8>>>(16);
+    tokio::spawn(async move {
+        if let Err(e) = write_export(&pool, user_id, format, &sender).await {
+            tracing::error!("Post export aborted: {:?}", e);
+            let _ = sender.send(Err(e)).await;
+        }
+    });
+
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    let (content_type, filename) = match format {
+        TransferFormat::Json => ("application/json", "posts.json"),
+        TransferFormat::Csv => ("text/csv; charset=utf-8", "posts.csv"),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+async fn write_export(
+    pool: &PgPool,
+    user_id: i32,
+    format: TransferFormat,
+    sender: &tokio::sync::mpsc::Sender<anyhow::Result<Vec<u8>>>,
+) -> anyhow::Result<()> {
+    let mut records = sqlx::query_as!(
+        PostRecord,
+        r#"SELECT id as "id?", post_type, description, categories, pin_code, status as "status?",
+                expires_at, created_at as "created_at?", updated_at as "updated_at?"
+         FROM posts
+         WHERE user_id = $1 AND deleted_at IS NULL
+         ORDER BY created_at, id"#,
+        user_id
+    )
+    .fetch(pool);
+
+    let mut chunk = match format {
+        TransferFormat::Json => b"[".to_vec(),
+        TransferFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(CSV_HEADERS)?;
+            writer.into_inner()?
+        }
+    };
+
+    let mut first = true;
+    while let Some(record) = records.next().await {
+        let record = record?;
+        match format {
+            TransferFormat::Json => {
+                if !first {
+                    chunk.push(b',');
+                }
+                chunk.extend(serde_json::to_vec(&record)?);
+            }
+            TransferFormat::Csv => chunk.extend(csv_line(record)?),
+        }
+        first = false;
+
+        // The client went away; stop reading
+        if sender.send(Ok(std::mem::take(&mut chunk))).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    if format == TransferFormat::Json {
+        chunk.push(b']');
+    }
+    let _ = sender.send(Ok(chunk)).await;
+
+    Ok(())
+}
+
+fn bad_request(message: String) -> AppError {
+    AppError::HttpError(StatusCode::BAD_REQUEST, anyhow::anyhow!(message))
+}
+
+// Each entry is a row that either parsed or the reason it did not
+fn parse_records(
+    format: TransferFormat,
+    body: &str,
+) -> Result<Vec<Result<PostRecord, String>>, AppError> {
+    match format {
+        TransferFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_str(body)
+                .map_err(|e| bad_request(format!("Body must be a JSON array of posts: {}", e)))?;
+            Ok(values
+                .into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+                .collect())
+        }
+        TransferFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(body.as_bytes());
+            let headers = reader
+                .headers()
+                .map_err(|e| bad_request(format!("Invalid CSV header: {}", e)))?;
+            for required in ["post_type", "description", "categories"] {
+                if !headers.iter().any(|h| h == required) {
+                    return Err(bad_request(format!("Missing CSV column {}", required)));
+                }
+            }
+            Ok(reader
+                .deserialize::<CsvPostRecord>()
+                .map(|row| row.map(PostRecord::from).map_err(|e| e.to_string()))
+                .collect())
+        }
+    }
+}
+
+// Same checks as create_post, collected per field like a PATCH
+async fn validate_record(
+    pool: &PgPool,
+    record: PostRecord,
+    default_expiry: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Result<NewPost, Vec<String>>, AppError> {
+    let mut errors = Vec::new();
+
+    if record.description.trim().is_empty() {
+        errors.push("description: cannot be empty".to_string());
+    }
+
+    let post_type = match record.post_type.trim().to_ascii_lowercase().as_str() {
+        "offer" => Some(PostType::Offer),
+        "request" => Some(PostType::Request),
+        _ => {
+            errors.push(format!(
+                "post_type: must be offer or request, not \"{}\"",
+                record.post_type
+            ));
+            None
+        }
+    };
+
+    if record.categories.is_empty() {
+        errors.push("categories: at least one category is required".to_string());
+    } else {
+        match validate_categories(pool, &record.categories).await {
+            Ok(()) => {}
+            Err(AppError::HttpError(_, e)) => errors.push(format!("categories: {}", e)),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let pin_code = record.pin_code.as_deref().and_then(normalize_pin_code);
+    if let Some(ref pin) = pin_code
+        && let Some(problem) = pin_code_problem(pool, pin).await?
+    {
+        errors.push(format!("pin_code: {}", problem));
+    }
+
+    // An exported post that has since expired comes back with a fresh expiry, like a new post
+    let expired = record
+        .status
+        .as_deref()
+        .is_some_and(|status| status.trim().eq_ignore_ascii_case("expired"));
+
+    let expires_at = match record.expires_at {
+        Some(_) if expired => default_expiry,
+        Some(expires_at) => match validate_expiry(expires_at) {
+            Ok(expires_at) => Some(expires_at),
+            Err(AppError::HttpError(_, e)) => {
+                errors.push(format!("expires_at: {}", e));
+                None
+            }
+            Err(e) => return Err(e),
+        },
+        None => default_expiry,
+    };
+
+    match post_type {
+        Some(post_type) if errors.is_empty() => Ok(Ok(NewPost {
+            description: record.description,
+            categories: record.categories,
+            post_type,
+            pin_code,
+            expires_at,
+        })),
+        _ => Ok(Err(errors)),
+    }
+}
+
+// Posts are told apart by type and description when importing
+fn duplicate_key(record: &PostRecord) -> (String, String) {
+    (
+        record.post_type.trim().to_ascii_lowercase(),
+        record.description.trim().to_string(),
+    )
+}
+
+pub async fn import_posts(
+    State(pool): State<PgPool>,
+    session: Session,
+    Query(query): Query<TransferQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ImportReport>, AppError> {
+    let user_id = get_my_user_id(session).await?.0;
+
+    let format = query.format.unwrap_or_else(|| {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if content_type.starts_with("text/csv") {
+            TransferFormat::Csv
+        } else {
+            TransferFormat::Json
+        }
+    });
+
+    let records = parse_records(format, &body)?;
+    if records.len() > MAX_IMPORT_ROWS {
+        return Err(bad_request(format!(
+            "At most {} posts can be imported at once",
+            MAX_IMPORT_ROWS
+        )));
+    }
+
+    Ok(Json(import_records(&pool, user_id, records).await?))
+}
+
+// Creates a post per valid row in one transaction. Invalid rows are reported and left out.
+// Rows repeating an existing post (e.g. re-importing an export) are skipped before they are
+// validated, so posts that would no longer pass the checks still round-trip.
+async fn import_records(
+    pool: &PgPool,
+    user_id: i32,
+    records: Vec<Result<PostRecord, String>>,
+) -> Result<ImportReport, AppError> {
+    let default_expiry = ExpiryConfig::from_env()
+        .map_err(|e| AppError::HttpError(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .default_expiry();
+
+    let mut tx = pool.begin().await?;
+
+    let mut seen: HashSet<(String, String)> = sqlx::query!(
+        "SELECT post_type, description FROM posts WHERE user_id = $1 AND deleted_at IS NULL",
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|p| (p.post_type, p.description.trim().to_string()))
+    .collect();
+
+    let mut rows = Vec::with_capacity(records.len());
+
+    for (i, record) in records.into_iter().enumerate() {
+        let report = |outcome, id, messages| ImportRowReport {
+            row: i + 1,
+            outcome,
+            id,
+            messages,
+        };
+
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                rows.push(report(ImportOutcome::Failed, None, vec![e]));
+                continue;
+            }
+        };
+
+        let key = duplicate_key(&record);
+        if seen.contains(&key) {
+            rows.push(report(
+                ImportOutcome::Skipped,
+                None,
+                vec![format!(
+                    "Duplicate of an existing {} with the same description",
+                    key.0
+                )],
+            ));
+            continue;
+        }
+
+        let new_post = match validate_record(pool, record, default_expiry).await? {
+            Ok(new_post) => new_post,
+            Err(errors) => {
+                rows.push(report(ImportOutcome::Failed, None, errors));
+                continue;
+            }
+        };
+        seen.insert(key);
+
+        let post_type = new_post.post_type.to_string();
+
+        let id = sqlx::query_scalar!(
+            "INSERT INTO posts (description, categories, user_id, post_type, pin_code, expires_at)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING id",
+            new_post.description,
+            &new_post.categories,
+            user_id,
+            post_type,
+            new_post.pin_code,
+            new_post.expires_at
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        rows.push(report(ImportOutcome::Created, Some(id), Vec::new()));
+    }
+
+    tx.commit().await?;
+
+    let count = |outcome| rows.iter().filter(|r| r.outcome == outcome).count();
+
+    Ok(ImportReport {
+        created: count(ImportOutcome::Created),
+        skipped: count(ImportOutcome::Skipped),
+        failed: count(ImportOutcome::Failed),
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_user(pool: &PgPool, email: &str) -> i32 {
+        sqlx::query_scalar("INSERT INTO users (email, password_hash) VALUES ($1, '') RETURNING id")
+            .bind(email)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn export_json(pool: &PgPool, user_id: i32) -> String {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(64);
+        write_export(pool, user_id, TransferFormat::Json, &sender)
+            .await
+            .unwrap();
+        drop(sender);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            body.extend(chunk.unwrap());
+        }
+        String::from_utf8(body).unwrap()
+    }
+
+    // One open post and one the sweeper has already expired
+    async fn seed_posts(pool: &PgPool, user_id: i32) {
+        sqlx::query(
+            "INSERT INTO posts (description, categories, user_id, post_type, status, expires_at) VALUES
+                ('Teach pasta', '{Cooking}', $1, 'offer', 'open', NOW() + INTERVAL '30 days'),
+                ('Learn guitar', '{Cooking}', $1, 'request', 'expired', NOW() - INTERVAL '1 day')",
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test]
+    async fn reimporting_an_export_skips_every_post(pool: PgPool) {
+        let user_id = create_user(&pool, "a@example.com").await;
+        seed_posts(&pool, user_id).await;
+
+        let body = export_json(&pool, user_id).await;
+        let records = parse_records(TransferFormat::Json, &body).unwrap();
+        let report = import_records(&pool, user_id, records).await.unwrap();
+
+        assert_eq!(report.skipped, 2, "{:?}", report.rows);
+        assert_eq!(report.created, 0);
+        assert_eq!(report.failed, 0);
+    }
+
+    #[sqlx::test]
+    async fn expired_posts_import_with_a_fresh_expiry(pool: PgPool) {
+        let owner = create_user(&pool, "a@example.com").await;
+        let other = create_user(&pool, "b@example.com").await;
+        seed_posts(&pool, owner).await;
+
+        let body = export_json(&pool, owner).await;
+        let records = parse_records(TransferFormat::Json, &body).unwrap();
+        let report = import_records(&pool, other, records).await.unwrap();
+
+        assert_eq!(report.created, 2, "{:?}", report.rows);
+        assert_eq!(report.failed, 0);
+
+        let expires_at: Option<chrono::DateTime<chrono::Utc>> = sqlx::query_scalar(
+            "SELECT expires_at FROM posts WHERE user_id = $1 AND description = 'Learn guitar'",
+        )
+        .bind(other)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(expires_at.is_none_or(|expires_at| expires_at > chrono::Utc::now()));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::post_images::discard_images;
+use anyhow::Result;
+use chrono::Duration;
+use sqlx::PgPool;
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+#[derive(Debug, Clone)]
+pub struct TrashConfig {
+    pub retention: Duration,
+}
+
+impl TrashConfig {
+    pub fn from_env() -> Result<Self> {
+        let retention_days = match std::env::var("TRASH_RETENTION_DAYS") {
+            Ok(days) => days.parse::<i64>().map_err(|_| {
+                anyhow::anyhow!("TRASH_RETENTION_DAYS must be a whole number of days")
+            })?,
+            Err(_) => DEFAULT_RETENTION_DAYS,
+        };
+
+        Ok(Self {
+            retention: Duration::days(retention_days.max(0)),
+        })
+    }
+}
+
+pub fn spawn_trash_purger(pool: PgPool, config: TrashConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match purge_trashed_posts(&pool, config.retention).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Purged {} trashed posts", count),
+                Err(e) => tracing::error!("Failed to purge trashed posts: {:?}", e),
+            }
+        }
+    });
+}
+
+async fn purge_trashed_posts(pool: &PgPool, retention: Duration) -> Result<u64> {
+    let cutoff = chrono::Utc::now() - retention;
+
+    let mut tx = pool.begin().await?;
+
+    // Locked so a post restored meanwhile is neither purged nor stripped of its images
+    let post_ids = sqlx::query_scalar!(
+        "SELECT id FROM posts WHERE deleted_at < $1 FOR UPDATE",
+        cutoff
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Image rows cascade with the post, but the uploads themselves live on Cloudinary
+    let image_ids = sqlx::query_scalar!(
+        "SELECT public_id FROM post_images WHERE post_id = ANY($1)",
+        &post_ids
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let result = sqlx::query!("DELETE FROM posts WHERE id = ANY($1)", &post_ids)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    discard_images(image_ids).await;
+
+    Ok(result.rows_affected())
+}
@@ -1,7 +1,25 @@
 import { PUBLIC_BACKEND_URL } from '$env/static/public';
-import type { Post, NewPost, AuthResponse, LoginRequest, UserProfile, Category } from '$lib/types';
+import type { Post, NewPost, AuthResponse, LoginRequest, UserProfile, Category, CategoryEntry, Page, PostFilter, PostStatus, PostRevision, RevisionDiff, PostMatch, PostType, PostFeatureCollection, PostCounts, GeoPlace, TransferFormat, ImportReport, Booking, TimeSlot, CreditBalance, CreditTransaction, Review, BookmarkResponse, FollowResponse, Conversation, Message, UnreadCount } from '$lib/types';
+
+// Builds the `?limit=&after=` query string understood by every paginated listing route,
+// plus any community filters
+function pageQuery(after?: string, limit?: number, filter?: PostFilter): string {
+    const params = new URLSearchParams();
+    if (limit) params.append('limit', limit.toString());
+    if (after) params.append('after', after);
+    if (filter?.post_type) params.append('post_type', filter.post_type);
+    if (filter?.categories?.length) params.append('categories', filter.categories.join(','));
+    if (filter?.category_match) params.append('category_match', filter.category_match);
+    if (filter?.pin_code) params.append('pin_code', filter.pin_code);
+    if (filter?.status) params.append('status', filter.status);
+    if (filter?.sort) params.append('sort', filter.sort);
+    if (filter?.near) params.append('near', filter.near);
+    if (filter?.radius_km) params.append('radius_km', filter.radius_km.toString());
+    const query = params.toString();
+    return query ? `?${query}` : '';
+}
 
-export async function createPost(description: string, categories: Category[], post_type: 'offer' | 'request', pin_code?: string): Promise<Post> {
+export async function createPost(description: string, categories: Category[], post_type: 'offer' | 'request', pin_code?: string, images: string[] = []): Promise<Post> {
     const formData = new URLSearchParams();
     formData.append('description', description);
     
@@ -9,6 +27,8 @@ export async function createPost(description: string, categories: Category[], po
     formData.append('categories', JSON.stringify(categories));
     formData.append('post_type', post_type);
     if (pin_code) formData.append('pin_code', pin_code);
+    // Base64 data URLs, uploaded by the backend in this order
+    if (images.length > 0) formData.append('images', JSON.stringify(images));
 
     const response = await fetch(`${PUBLIC_BACKEND_URL}posts/create`, {
         method: "POST",
@@ -26,8 +46,20 @@ export async function createPost(description: string, categories: Category[], po
     return response.json();
 }
 
-export async function getMyPosts(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}posts`, {
+export async function getCategories(): Promise<CategoryEntry[]> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}categories`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        throw new Error(`Failed to fetch categories: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function getMyPosts(after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts${pageQuery(after, limit)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -39,8 +71,8 @@ export async function getMyPosts(): Promise<Post[]> {
     return response.json();
 }
 
-export async function getUserPosts(id: number): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}foreignposts/${id}`, {
+export async function getUserPosts(id: number, after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}foreignposts/${id}${pageQuery(after, limit)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -52,8 +84,8 @@ export async function getUserPosts(id: number): Promise<Post[]> {
     return response.json();
 }
 
-export async function getCommunityPosts(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}community`, {
+export async function getCommunityPosts(filter?: PostFilter, after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community${pageQuery(after, limit, filter)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -65,8 +97,8 @@ export async function getCommunityPosts(): Promise<Post[]> {
     return response.json();
 }
 
-export async function getCommunityOffers(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}community/offers`, {
+export async function getCommunityOffers(filter?: PostFilter, after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/offers${pageQuery(after, limit, filter)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -78,8 +110,8 @@ export async function getCommunityOffers(): Promise<Post[]> {
     return response.json();
 }
 
-export async function getCommunityRequests(): Promise<Post[]> {
-    const response = await fetch(`${PUBLIC_BACKEND_URL}community/requests`, {
+export async function getCommunityRequests(filter?: PostFilter, after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/requests${pageQuery(after, limit, filter)}`, {
         method: "GET",
         credentials: "include"
     });
@@ -91,16 +123,77 @@ export async function getCommunityRequests(): Promise<Post[]> {
     return response.json();
 }
 
+export async function getFollowingPosts(filter?: PostFilter, after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/following${pageQuery(after, limit, filter)}`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorData = await response.json().catch(() => ({}));
+        throw new Error(errorData.message || `Failed to fetch following feed: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+export async function searchCommunityPosts(q: string, after?: string, limit?: number): Promise<Page<Post>> {
+    const params = new URLSearchParams({ q });
+    if (limit) params.append('limit', limit.toString());
+    if (after) params.append('after', after);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/search?${params.toString()}`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to search posts: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
 export async function updatePost(postToUpdate: Post): Promise<Post> {
     const response = await fetch(`${PUBLIC_BACKEND_URL}posts/update`, {
         method: 'POST',
         credentials: 'include',
         headers: {
-            'Content-Type': 'application/json'
+            'Content-Type': 'application/json',
+            // Rejected with 412 if the post changed elsewhere since we loaded it
+            ...(postToUpdate.version ? { 'If-Match': `"${postToUpdate.version}"` } : {})
         },
         body: JSON.stringify(postToUpdate)
     });
 
+    if (response.status === 412) {
+        throw new Error('This post was changed on another device. Reload it and try again.');
+    }
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to update post: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+// Fields set to null are cleared, omitted fields are left untouched
+export async function patchPost(
+    id: number,
+    patch: Partial<Pick<Post, 'description' | 'categories' | 'post_type'>> & { pin_code?: string | null },
+    version?: number
+): Promise<Post> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}`, {
+        method: 'PATCH',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/merge-patch+json',
+            ...(version ? { 'If-Match': `"${version}"` } : {})
+        },
+        body: JSON.stringify(patch)
+    });
+
+    if (response.status === 412) {
+        throw new Error('This post was changed on another device. Reload it and try again.');
+    }
     if (!response.ok) {
         const errorText = await response.text();
         throw new Error(`Failed to update post: ${response.status} ${response.statusText} - ${errorText}`);
@@ -108,6 +201,352 @@ export async function updatePost(postToUpdate: Post): Promise<Post> {
     return response.json();
 }
 
+export async function changePostStatus(id: number, status: PostStatus): Promise<Post> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/status`, {
+        method: 'POST',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify({ status })
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to change post status: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function getBookings(scope: 'upcoming' | 'past' = 'upcoming', role?: 'learner' | 'teacher', after?: string, limit?: number): Promise<Page<Booking>> {
+    const params = new URLSearchParams({ scope });
+    if (role) params.append('role', role);
+    if (limit) params.append('limit', limit.toString());
+    if (after) params.append('after', after);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}bookings?${params.toString()}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch bookings: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+async function bookingRequest(path: string, body?: unknown): Promise<Booking> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}${path}`, {
+        method: 'POST',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: body === undefined ? undefined : JSON.stringify(body)
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Booking request failed: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function requestBooking(postId: number, slots: TimeSlot[], message = ''): Promise<Booking> {
+    return bookingRequest('bookings', { post_id: postId, message, slots });
+}
+
+export async function proposeBookingSlots(id: number, slots: TimeSlot[]): Promise<Booking> {
+    return bookingRequest(`bookings/${id}/propose`, { slots });
+}
+
+export async function acceptBooking(id: number, slotId: number): Promise<Booking> {
+    return bookingRequest(`bookings/${id}/accept`, { slot_id: slotId });
+}
+
+export async function declineBooking(id: number): Promise<Booking> {
+    return bookingRequest(`bookings/${id}/decline`);
+}
+
+export async function cancelBooking(id: number): Promise<Booking> {
+    return bookingRequest(`bookings/${id}/cancel`);
+}
+
+export async function completeBooking(id: number): Promise<Booking> {
+    return bookingRequest(`bookings/${id}/complete`);
+}
+
+export async function reviewBooking(id: number, rating: number, text = ''): Promise<Review> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}bookings/${id}/review`, {
+        method: 'POST',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify({ rating, text })
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to submit review: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+async function bookmarkRequest(postId: number, method: 'POST' | 'DELETE'): Promise<BookmarkResponse> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}bookmarks/${postId}`, {
+        method,
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to update bookmark: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export const addBookmark = (postId: number) => bookmarkRequest(postId, 'POST');
+export const removeBookmark = (postId: number) => bookmarkRequest(postId, 'DELETE');
+
+async function followRequest(userId: number, method: 'POST' | 'DELETE'): Promise<FollowResponse> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}follows/${userId}`, {
+        method,
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to update follow: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export const followUser = (userId: number) => followRequest(userId, 'POST');
+export const unfollowUser = (userId: number) => followRequest(userId, 'DELETE');
+
+export async function getBookmarks(after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}bookmarks${pageQuery(after, limit)}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch bookmarks: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function getConversations(after?: string, limit?: number): Promise<Page<Conversation>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}conversations${pageQuery(after, limit)}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch conversations: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+async function conversationRequest(path: string, body?: unknown): Promise<Conversation> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}${path}`, {
+        method: 'POST',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: body === undefined ? undefined : JSON.stringify(body)
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Conversation request failed: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export function startConversation(userId: number, postId?: number, message?: string): Promise<Conversation> {
+    return conversationRequest('conversations', { user_id: userId, post_id: postId ?? null, message });
+}
+
+export const markConversationRead = (id: number) => conversationRequest(`conversations/${id}/read`);
+
+export async function getConversation(id: number): Promise<Conversation> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}conversations/${id}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch conversation: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function getMessages(id: number, after?: string, limit?: number): Promise<Page<Message>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}conversations/${id}/messages${pageQuery(after, limit)}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch messages: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function sendMessage(id: number, body: string): Promise<Message> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}conversations/${id}/messages`, {
+        method: 'POST',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify({ body })
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to send message: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function getUnreadCount(): Promise<UnreadCount> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}conversations/unread`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch unread count: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function renewPost(id: number, expiresAt?: string): Promise<Post> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/renew`, {
+        method: 'POST',
+        credentials: 'include',
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify(expiresAt ? { expires_at: expiresAt } : {})
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to renew post: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+// Open community posts; public, so the login page can show them before sign-in
+export async function getCommunityStats(): Promise<PostCounts> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community/stats`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        throw new Error(`Failed to fetch community stats: ${response.statusText}`);
+    }
+    return response.json();
+}
+
+// Clustered at low zoom; bbox is Leaflet's map.getBounds().toBBoxString()
+export async function getCommunityGeoJson(bbox: string, zoom: number, postType?: PostType): Promise<PostFeatureCollection> {
+    const params = new URLSearchParams({ bbox, zoom: Math.round(zoom).toString() });
+    if (postType) params.append('post_type', postType);
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}community.geojson?${params.toString()}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch map data: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+// Both lookups go through the backend, which caches provider answers; null means nothing was found
+async function fetchGeoPlace(path: string): Promise<GeoPlace | null> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}${path}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (response.status === 404) return null;
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to geocode: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function geocodePinCode(pinCode: string): Promise<GeoPlace | null> {
+    return fetchGeoPlace(`geo/pincode/${encodeURIComponent(pinCode)}`);
+}
+
+export async function reverseGeocode(lat: number, lon: number): Promise<GeoPlace | null> {
+    const params = new URLSearchParams({ lat: lat.toString(), lon: lon.toString() });
+    return fetchGeoPlace(`geo/reverse?${params.toString()}`);
+}
+
+export async function getPostMatches(id: number, limit?: number): Promise<PostMatch[]> {
+    const query = limit ? `?limit=${limit}` : '';
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/matches${query}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch matches: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+// Resolves to the file contents; CSV puts all categories in one ";"-separated column
+export async function exportPosts(format: TransferFormat = 'json'): Promise<Blob> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/export?format=${format}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to export posts: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.blob();
+}
+
+export async function importPosts(contents: string, format: TransferFormat): Promise<ImportReport> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/import?format=${format}`, {
+        method: 'POST',
+        headers: {
+            'Content-Type': format === 'csv' ? 'text/csv' : 'application/json'
+        },
+        body: contents,
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to import posts: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
 export async function deletePost(id: number): Promise<void> {
     const response = await fetch(`${PUBLIC_BACKEND_URL}posts/delete/${id}`, {
         method: "DELETE",
@@ -120,6 +559,61 @@ export async function deletePost(id: number): Promise<void> {
     }
 }
 
+export async function getTrash(after?: string, limit?: number): Promise<Page<Post>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/trash${pageQuery(after, limit)}`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch trash: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function restorePost(id: number): Promise<Post> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/restore`, {
+        method: "POST",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to restore post: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function getPostRevisions(id: number): Promise<PostRevision[]> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/revisions`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch revisions: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function diffPostRevisions(id: number, from: number, to?: number): Promise<RevisionDiff> {
+    const params = new URLSearchParams({ from: from.toString() });
+    if (to !== undefined) params.append('to', to.toString());
+
+    const response = await fetch(`${PUBLIC_BACKEND_URL}posts/${id}/revisions/diff?${params.toString()}`, {
+        method: "GET",
+        credentials: "include"
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to diff revisions: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
 export async function login(email: string, password: string): Promise<AuthResponse> {
     const formData = new URLSearchParams();
     formData.append('email', email);
@@ -228,6 +722,32 @@ export async function getUserProfile(userId: number): Promise<UserProfile> {
     return response.json();
 }
 
+export async function getMyCredits(): Promise<CreditBalance> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/myprofile/credits`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch credits: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
+export async function getMyCreditHistory(after?: string, limit?: number): Promise<Page<CreditTransaction>> {
+    const response = await fetch(`${PUBLIC_BACKEND_URL}auth/myprofile/credits/history${pageQuery(after, limit)}`, {
+        method: 'GET',
+        credentials: 'include'
+    });
+
+    if (!response.ok) {
+        const errorText = await response.text();
+        throw new Error(`Failed to fetch credit history: ${response.status} ${response.statusText} - ${errorText}`);
+    }
+    return response.json();
+}
+
 export async function updateProfilePicture(profilePicture: string): Promise<any> {
     const formData = new URLSearchParams();
     formData.append('profile_picture', profilePicture);
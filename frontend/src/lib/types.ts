@@ -1,5 +1,7 @@
 export type PostType = 'offer' | 'request';
 
+export type PostStatus = 'open' | 'in_progress' | 'fulfilled' | 'closed' | 'expired';
+
 // Comprehensive category enum with 100+ categories
 export const CATEGORIES = [
     // Technology & Programming
@@ -56,15 +58,156 @@ export const CATEGORIES = [
 
 export type Category = typeof CATEGORIES[number];
 
+// Category as served by the backend's canonical taxonomy (`GET /categories`)
+export interface CategoryEntry {
+    name: Category;
+    group_name: string;
+}
+
+export interface PinLocation {
+    locality: string;
+    district: string;
+    state: string;
+}
+
+export interface PostImage {
+    id: number;
+    url: string;
+    position: number;
+}
+
 export interface Post {
     id: number;
     description: string;
+    description_html: string; // Sanitised server-side render of the Markdown description
     categories: Category[];
     user_id: number;
     post_type: PostType;
     pin_code?: string;
+    location?: PinLocation | null; // Resolved from the server's pin-code gazetteer
+    user_name?: string;
+    profile_picture?: string;
+    status: PostStatus;
+    version: number;
+    created_at: string;
+    updated_at: string;
+    expires_at?: string;
+    deleted_at?: string; // Only present on posts in the trash
+    snippet?: string; // HTML-escaped excerpt with <mark> highlights, search results only
+    distance_km?: number; // Only on listings filtered with `near`
+    bookmarked?: boolean; // Only on community listings, for the logged-in user
+    images: PostImage[]; // On update, the images to keep; left out, the current ones stay
+    new_images?: string[]; // Base64 data URLs to append on update
+}
+
+export interface PostFilter {
+    post_type?: PostType;
+    categories?: Category[];
+    category_match?: 'any' | 'all';
+    pin_code?: string;
+    status?: PostStatus;
+    sort?: PostSort;
+    near?: string; // Pin code or "lat,lon"; overrides sort with nearest first
+    radius_km?: number;
+}
+
+export type PostSort = 'newest' | 'oldest' | 'updated';
+
+export interface Page<T> {
+    items: T[];
+    next_cursor?: string;
+}
+
+export interface PostCounts {
+    total: number;
+    offers: number;
+    requests: number;
+}
+
+export interface PostRevision {
+    post_id: number;
+    revision: number;
+    description: string;
+    categories: Category[];
+    post_type: PostType;
+    pin_code?: string;
+    edited_at: string;
+    replaced_at?: string;
+    replaced_by?: number;
+}
+
+export interface RevisionDiff {
+    post_id: number;
+    from: number;
+    to: number;
+    changes: { field: string; before: unknown; after: unknown }[];
+}
+
+export interface ClusterProperties {
+    cluster: true;
+    count: number;
+    offers: number;
+    requests: number;
+    top_categories: Category[];
+}
+
+export interface PostPointProperties {
+    cluster: false;
+    id: number;
+    description: string;
+    description_html: string;
+    categories: Category[];
+    post_type: PostType;
+    pin_code: string;
+    location: PinLocation;
+    user_id: number;
     user_name?: string;
     profile_picture?: string;
+    created_at: string;
+}
+
+// What a map popup shows of a post, whether it came from a listing or from /community.geojson
+export type MapPost = Pick<Post, 'id' | 'post_type' | 'description_html' | 'categories' | 'pin_code' | 'user_id' | 'user_name' | 'profile_picture'> & {
+    images?: PostImage[];
+};
+
+export interface PostFeatureCollection {
+    type: 'FeatureCollection';
+    features: {
+        type: 'Feature';
+        id?: number;
+        geometry: { type: 'Point'; coordinates: [number, number] }; // [lon, lat]
+        properties: ClusterProperties | PostPointProperties;
+    }[];
+}
+
+export interface GeoPlace {
+    latitude: number;
+    longitude: number;
+    display_name: string;
+    postcode?: string | null;
+}
+
+export interface PostMatch {
+    post: Post;
+    score: number; // 0..1, higher is a better fit
+    reasons: string[];
+}
+
+export type TransferFormat = 'json' | 'csv';
+
+export interface ImportRowReport {
+    row: number; // 1-based, not counting the CSV header
+    outcome: 'created' | 'skipped' | 'failed';
+    id: number | null;
+    messages: string[];
+}
+
+export interface ImportReport {
+    created: number;
+    skipped: number;
+    failed: number;
+    rows: ImportRowReport[];
 }
 
 export interface NewPost {
@@ -79,15 +222,121 @@ export interface NewUser {
     password: string;
     name?: string;
     pin_code?: string;
+    location?: PinLocation | null;
     profile_picture?: string;
 }
 
+export type BookingStatus = 'requested' | 'proposed' | 'accepted' | 'declined' | 'cancelled' | 'completed';
+
+export interface TimeSlot {
+    starts_at: string;
+    ends_at: string;
+}
+
+export interface BookingSlot extends TimeSlot {
+    id: number;
+    proposed_by: number;
+}
+
+export interface Booking {
+    id: number;
+    post_id: number | null; // Null once the offer has been purged from the trash
+    post_description: string;
+    learner_id: number;
+    learner_name: string | null;
+    teacher_id: number; // Owner of the offer
+    teacher_name: string | null;
+    message: string;
+    status: BookingStatus; // 'requested' waits on the teacher, 'proposed' on the learner
+    starts_at: string | null; // Set once a slot is accepted
+    ends_at: string | null;
+    slots: BookingSlot[]; // The latest open proposal
+    learner_confirmed_at: string | null; // Completion settles once both sides have confirmed
+    teacher_confirmed_at: string | null;
+    created_at: string;
+    updated_at: string;
+}
+
+export interface CreditBalance {
+    balance_hours: number;
+    committed_hours: number; // Accepted sessions as a learner, not yet settled
+    available_hours: number;
+}
+
+export interface CreditTransaction {
+    id: number;
+    kind: 'opening' | 'exchange';
+    booking_id: number | null;
+    hours: number; // Positive when received, negative when spent
+    counterparty_id: number | null; // null for the bank
+    counterparty_name: string | null;
+    created_at: string;
+}
+
+export interface RatingSummary {
+    average: number | null; // null until the first review
+    count: number;
+}
+
+export interface Review {
+    id: number;
+    booking_id: number;
+    post_id: number | null;
+    reviewer_id: number;
+    reviewer_name: string | null;
+    rating: number; // 1 to 5 stars
+    text: string;
+    created_at: string;
+}
+
+export interface BookmarkResponse {
+    post_id: number;
+    bookmarked: boolean;
+}
+
+export interface FollowResponse {
+    user_id: number;
+    following: boolean;
+}
+
+export interface Message {
+    id: number;
+    conversation_id: number;
+    sender_id: number;
+    body: string;
+    created_at: string;
+    read_at: string | null;
+}
+
+export interface Conversation {
+    id: number;
+    other_user_id: number;
+    other_user_name: string | null;
+    other_profile_picture: string | null;
+    post_id: number | null; // Set when the conversation is about a post
+    post_description: string | null;
+    last_message: Message | null;
+    unread_count: number; // Messages from the other user not yet marked read
+    created_at: string;
+    last_message_at: string;
+}
+
+export interface UnreadCount {
+    conversations: number;
+    messages: number;
+}
+
 export interface UserProfile {
     id: number;
     email: string;
     name?: string;
     pin_code?: string;
     profile_picture?: string;
+    rating: RatingSummary;
+    reviews: Review[]; // Most recent first
+    follower_count: number;
+    following_count: number;
+    post_counts: PostCounts; // Posts outside the trash
 }
 
 export interface AuthResponse {